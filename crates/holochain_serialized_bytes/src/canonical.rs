@@ -0,0 +1,269 @@
+//! Canonical messagepack.
+//!
+//! `encode` writes whatever the `Serialize` implementation asks for, which is not always the same
+//! bytes for the same value, e.g. a `HashMap` serializes its entries in iteration order.
+//! Anything that hashes or signs bytes needs equal values to produce equal bytes.
+//!
+//! The canonical form of a messagepack value is:
+//! - integers use the smallest marker that fits, unsigned for non-negative values and signed for
+//!   negative values (exactly what rmp_serde does for rust integers)
+//! - str, bin, ext, array and map lengths use the smallest marker that fits
+//! - NaN is always the quiet NaN with an empty payload and positive sign for its width
+//! - negative zero is always written as positive zero
+//! - map keys are unique and sorted by their canonical encoded bytes
+//!
+//! Float widths are never changed, an `f32` stays an `f32`.
+
+use crate::msgpack::{read_token, walk, write_head, Event, Kind, ReadError};
+use crate::DeserializeError;
+use crate::SerializedBytesError;
+use serde::Deserialize;
+use std::cmp::Ordering;
+
+const CANONICAL_NAN_F32: u32 = 0x7fc0_0000;
+const CANONICAL_NAN_F64: u64 = 0x7ff8_0000_0000_0000;
+
+pub(crate) fn canonical_f32(f: f32) -> f32 {
+    if f.is_nan() {
+        f32::from_bits(CANONICAL_NAN_F32)
+    } else if f == 0.0 {
+        0.0
+    } else {
+        f
+    }
+}
+
+pub(crate) fn canonical_f64(f: f64) -> f64 {
+    if f.is_nan() {
+        f64::from_bits(CANONICAL_NAN_F64)
+    } else if f == 0.0 {
+        0.0
+    } else {
+        f
+    }
+}
+
+/// A rule of the canonical form that some bytes broke.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+pub enum CanonicalRule {
+    /// an integer wasn't written with the smallest marker for its value
+    IntegerMarker,
+    /// a str, bin, ext, array or map length wasn't written with the smallest marker
+    LengthMarker,
+    /// a NaN or negative zero float wasn't written in its canonical form
+    FloatForm,
+    /// a map key doesn't sort after the key before it
+    MapKeyOrder,
+    /// a map key is the same as the key before it
+    DuplicateMapKey,
+    /// there are more bytes after the end of the value
+    TrailingBytes,
+}
+
+impl std::fmt::Display for CanonicalRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            CanonicalRule::IntegerMarker => "integer not written with its smallest marker",
+            CanonicalRule::LengthMarker => "length not written with its smallest marker",
+            CanonicalRule::FloatForm => "float not in canonical form",
+            CanonicalRule::MapKeyOrder => "map keys not sorted by their encoded bytes",
+            CanonicalRule::DuplicateMapKey => "duplicate map key",
+            CanonicalRule::TrailingBytes => "trailing bytes after value",
+        })
+    }
+}
+
+/// Which canonical rule was broken and the offset of the first byte that broke it.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+pub struct CanonicalViolation {
+    pub rule: CanonicalRule,
+    pub offset: usize,
+}
+
+impl std::fmt::Display for CanonicalViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "non-canonical messagepack at byte {}: {}",
+            self.offset, self.rule
+        )
+    }
+}
+
+enum ValidateError {
+    Read(ReadError),
+    NonCanonical(CanonicalViolation),
+}
+
+impl From<ReadError> for ValidateError {
+    fn from(err: ReadError) -> Self {
+        ValidateError::Read(err)
+    }
+}
+
+fn violation(rule: CanonicalRule, offset: usize) -> ValidateError {
+    ValidateError::NonCanonical(CanonicalViolation { rule, offset })
+}
+
+fn validate(bytes: &[u8]) -> Result<(), ValidateError> {
+    let mut expected = Vec::with_capacity(9);
+    walk(bytes, 0, |event| match event {
+        Event::Token { token, .. } => {
+            let canonical_float = match token.kind {
+                Kind::F32(f) => f.to_bits() == canonical_f32(f).to_bits(),
+                Kind::F64(f) => f.to_bits() == canonical_f64(f).to_bits(),
+                _ => true,
+            };
+            if !canonical_float {
+                return Err(violation(CanonicalRule::FloatForm, token.offset));
+            }
+            expected.clear();
+            write_head(&mut expected, token.kind);
+            if expected[..] != bytes[token.offset..token.body_start()] {
+                let rule = match token.kind {
+                    Kind::UInt(_) | Kind::Int(_) => CanonicalRule::IntegerMarker,
+                    _ => CanonicalRule::LengthMarker,
+                };
+                return Err(violation(rule, token.offset));
+            }
+            Ok(())
+        }
+        // every key has already been checked as canonical so the raw bytes can be compared
+        Event::Key {
+            key,
+            prev: Some(prev),
+        } => match bytes[key.0..key.1].cmp(&bytes[prev.0..prev.1]) {
+            Ordering::Greater => Ok(()),
+            Ordering::Equal => Err(violation(CanonicalRule::DuplicateMapKey, key.0)),
+            Ordering::Less => Err(violation(CanonicalRule::MapKeyOrder, key.0)),
+        },
+        Event::Key { prev: None, .. } => Ok(()),
+    })
+    .and_then(|end| {
+        if end == bytes.len() {
+            Ok(())
+        } else {
+            Err(violation(CanonicalRule::TrailingBytes, end))
+        }
+    })
+}
+
+fn validate_error(err: ValidateError, type_name: &str) -> SerializedBytesError {
+    #[cfg(feature = "trace")]
+    tracing::warn!("Input is not canonical messagepack");
+    match err {
+        ValidateError::Read(err) => {
+            SerializedBytesError::Deserialize(DeserializeError::from_read(err, type_name))
+        }
+        ValidateError::NonCanonical(violation) => SerializedBytesError::NonCanonical(violation),
+    }
+}
+
+/// Check that `bytes` are exactly one messagepack value in canonical form,
+/// i.e. they are what `encode_canonical` would produce for the value they hold.
+#[cfg_attr(feature = "trace", tracing::instrument)]
+pub fn validate_canonical(bytes: &[u8]) -> Result<(), SerializedBytesError> {
+    validate(bytes).map_err(|err| validate_error(err, ""))
+}
+
+/// Decode the same way as `decode` but only if the input is in canonical form.
+///
+/// Use this for anything signed or hashed that comes from outside, so the bytes that were checked
+/// are the only bytes that could ever represent the decoded value.
+///
+/// Only bytes from `encode_canonical` are sure to be accepted. `encode`, `holochain_serial!` and
+/// `#[derive(SerializedBytes)]` write struct fields in the order they are declared, so their bytes
+/// for a struct with fields that aren't declared in sorted order are refused with
+/// `CanonicalRule::MapKeyOrder`, as are maps written in iteration order, e.g. a `HashMap`.
+#[cfg_attr(feature = "trace", tracing::instrument)]
+pub fn decode_canonical<'a, R, T>(input: &'a R) -> Result<T, SerializedBytesError>
+where
+    R: AsRef<[u8]> + ?Sized + std::fmt::Debug,
+    T: Deserialize<'a> + std::fmt::Debug,
+{
+    validate(input.as_ref()).map_err(|err| validate_error(err, std::any::type_name::<T>()))?;
+    crate::decode(input)
+}
+
+/// Serialize `val` the same way as `encode` then rewrite the bytes into canonical form.
+#[cfg_attr(feature = "trace", tracing::instrument)]
+pub fn encode_canonical<T: serde::Serialize + std::fmt::Debug>(
+    val: &T,
+) -> Result<Vec<u8>, SerializedBytesError> {
+    let bytes = crate::encode(val)?;
+    let ret = canonicalize(&bytes)?;
+    #[cfg(feature = "trace")]
+    tracing::trace!(
+        "Canonicalized {} input into {:?}",
+        std::any::type_name::<T>(),
+        ret
+    );
+    Ok(ret)
+}
+
+/// Rewrite a single complete messagepack value into its canonical form.
+pub(crate) fn canonicalize(bytes: &[u8]) -> Result<Vec<u8>, SerializedBytesError> {
+    let mut out = Vec::with_capacity(bytes.len());
+    let end = write_canonical(bytes, 0, &mut out)?;
+    if end != bytes.len() {
+        return Err(SerializedBytesError::Serialize(format!(
+            "{} unexpected bytes after the value ending at byte {}",
+            bytes.len() - end,
+            end
+        )));
+    }
+    Ok(out)
+}
+
+/// Write the canonical form of the value starting at `offset` and return the offset it ends at.
+fn write_canonical(
+    bytes: &[u8],
+    offset: usize,
+    out: &mut Vec<u8>,
+) -> Result<usize, SerializedBytesError> {
+    let token = read_token(bytes, offset)
+        .map_err(|err| SerializedBytesError::Serialize(err.to_string()))?;
+    let kind = match token.kind {
+        Kind::F32(f) => Kind::F32(canonical_f32(f)),
+        Kind::F64(f) => Kind::F64(canonical_f64(f)),
+        kind => kind,
+    };
+    write_head(out, kind);
+    out.extend_from_slice(token.payload(bytes));
+
+    let mut pos = token.end();
+    match token.kind {
+        Kind::Array(len) => {
+            for _ in 0..len {
+                pos = write_canonical(bytes, pos, out)?;
+            }
+        }
+        Kind::Map(len) => {
+            let mut entries = Vec::with_capacity(len);
+            for _ in 0..len {
+                let mut key = Vec::new();
+                pos = write_canonical(bytes, pos, &mut key)?;
+                let mut value = Vec::new();
+                pos = write_canonical(bytes, pos, &mut value)?;
+                entries.push((key, value));
+            }
+            entries.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+            if entries.windows(2).any(|w| w[0].0 == w[1].0) {
+                return Err(SerializedBytesError::Serialize(format!(
+                    "duplicate key in map at byte {}",
+                    token.offset
+                )));
+            }
+            for (key, value) in entries {
+                out.extend_from_slice(&key);
+                out.extend_from_slice(&value);
+            }
+        }
+        _ => {}
+    }
+    Ok(pos)
+}
@@ -0,0 +1,112 @@
+//! `tokio_util::codec` support, so async code can turn any `AsyncRead` or `AsyncWrite` into a
+//! `Stream` or `Sink` of `holochain_serial!` types with `FramedRead`, `FramedWrite` or `Framed`.
+
+use crate::framing::FrameError;
+use crate::FrameStatus;
+use crate::Framing;
+use crate::SerializedBytes;
+use crate::SerializedBytesError;
+use tokio_util::bytes::BytesMut;
+use tokio_util::codec::{Decoder, Encoder};
+
+/// Encodes and decodes `T` as `Framing` frames of `SerializedBytes`.
+///
+/// Decoded frames are split off the read buffer rather than copied out of it.
+///
+/// Errors are `E`, which needs to convert from io errors for `tokio_util`, from
+/// `SerializedBytesError` for frame errors, and from the errors of converting `T`. The default is
+/// `std::io::Error`s of kind `InvalidData` that hold the `SerializedBytesError`, otherwise use the
+/// `error` type of a `holochain_serial!` type that also converts from io errors.
+#[derive(Debug)]
+pub struct SerializedBytesCodec<T, E = std::io::Error> {
+    framing: Framing,
+    _t: std::marker::PhantomData<fn(T) -> Result<T, E>>,
+}
+
+impl<T, E> SerializedBytesCodec<T, E> {
+    pub fn new(framing: Framing) -> Self {
+        Self {
+            framing,
+            _t: std::marker::PhantomData,
+        }
+    }
+
+    pub fn framing(&self) -> &Framing {
+        &self.framing
+    }
+}
+
+impl<T, E> Default for SerializedBytesCodec<T, E> {
+    fn default() -> Self {
+        Self::new(Framing::default())
+    }
+}
+
+impl<T, E> Clone for SerializedBytesCodec<T, E> {
+    fn clone(&self) -> Self {
+        Self::new(self.framing)
+    }
+}
+
+impl<T, E> Encoder<T> for SerializedBytesCodec<T, E>
+where
+    SerializedBytes: TryFrom<T>,
+    E: From<<SerializedBytes as TryFrom<T>>::Error>
+        + From<SerializedBytesError>
+        + From<std::io::Error>,
+{
+    type Error = E;
+
+    fn encode(&mut self, item: T, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let sb = SerializedBytes::try_from(item)?;
+        let mut prefix = Vec::with_capacity(self.framing.prefix.max_len());
+        self.framing
+            .write_prefix(sb.bytes().len(), &mut prefix)
+            .map_err(SerializedBytesError::from)?;
+        dst.reserve(prefix.len() + sb.bytes().len());
+        dst.extend_from_slice(&prefix);
+        dst.extend_from_slice(sb.bytes());
+        Ok(())
+    }
+}
+
+impl<T, E> Decoder for SerializedBytesCodec<T, E>
+where
+    T: TryFrom<SerializedBytes>,
+    E: From<T::Error> + From<SerializedBytesError> + From<std::io::Error>,
+{
+    type Item = T;
+    type Error = E;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<T>, Self::Error> {
+        match self.framing.decode_frame(src)? {
+            FrameStatus::Complete { frame, .. } => Ok(Some(T::try_from(frame)?)),
+            FrameStatus::Incomplete { needed } => {
+                src.reserve(needed);
+                Ok(None)
+            }
+        }
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<T>, Self::Error> {
+        match self.decode(src)? {
+            Some(item) => Ok(Some(item)),
+            None if src.is_empty() => Ok(None),
+            None => {
+                let expected = match self
+                    .framing
+                    .read_prefix(src)
+                    .map_err(SerializedBytesError::from)?
+                {
+                    Ok((prefix_len, frame_len)) => prefix_len + frame_len,
+                    Err(needed) => src.len() + needed,
+                };
+                Err(SerializedBytesError::from(FrameError::Truncated {
+                    expected,
+                    got: src.len(),
+                })
+                .into())
+            }
+        }
+    }
+}
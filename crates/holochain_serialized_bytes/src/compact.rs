@@ -0,0 +1,173 @@
+//! The serializer behind `encode_compact`.
+//!
+//! rmp_serde writes either every struct as a map or every struct as an array. A compact type
+//! only chooses the encoding of its own fields, so this wraps the usual struct map serializer and
+//! writes only the outermost struct, or struct variant, as an array. Everything inside it,
+//! including other compact types, is written exactly as `encode` writes it.
+
+use rmp_serde::config::SerializerConfig;
+use serde::ser::{self, Serialize};
+use std::io::Write;
+
+type Inner<'a, W, C> = &'a mut rmp_serde::encode::Serializer<W, C>;
+type Error = rmp_serde::encode::Error;
+
+/// Writes the value it serializes as a compact type.
+pub(crate) struct Serializer<'a, W, C>(pub(crate) Inner<'a, W, C>);
+
+macro_rules! forward_serialize {
+    ($($method:ident($($arg:ident: $ty:ty),*) -> $ok:ty;)*) => {$(
+        fn $method(self, $($arg: $ty),*) -> Result<$ok, Error> {
+            self.0.$method($($arg),*)
+        }
+    )*};
+}
+
+impl<'a, W: Write, C: SerializerConfig> ser::Serializer for Serializer<'a, W, C> {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = <Inner<'a, W, C> as ser::Serializer>::SerializeSeq;
+    type SerializeTuple = <Inner<'a, W, C> as ser::Serializer>::SerializeTuple;
+    type SerializeTupleStruct = <Inner<'a, W, C> as ser::Serializer>::SerializeTupleStruct;
+    type SerializeTupleVariant = <Inner<'a, W, C> as ser::Serializer>::SerializeTupleVariant;
+    type SerializeMap = <Inner<'a, W, C> as ser::Serializer>::SerializeMap;
+    type SerializeStruct = Fields<'a, W, C>;
+    type SerializeStructVariant = Fields<'a, W, C>;
+
+    forward_serialize! {
+        serialize_bool(v: bool) -> ();
+        serialize_i8(v: i8) -> ();
+        serialize_i16(v: i16) -> ();
+        serialize_i32(v: i32) -> ();
+        serialize_i64(v: i64) -> ();
+        serialize_i128(v: i128) -> ();
+        serialize_u8(v: u8) -> ();
+        serialize_u16(v: u16) -> ();
+        serialize_u32(v: u32) -> ();
+        serialize_u64(v: u64) -> ();
+        serialize_u128(v: u128) -> ();
+        serialize_f32(v: f32) -> ();
+        serialize_f64(v: f64) -> ();
+        serialize_char(v: char) -> ();
+        serialize_str(v: &str) -> ();
+        serialize_bytes(v: &[u8]) -> ();
+        serialize_none() -> ();
+        serialize_unit() -> ();
+        serialize_unit_struct(name: &'static str) -> ();
+        serialize_unit_variant(name: &'static str, index: u32, variant: &'static str) -> ();
+        serialize_seq(len: Option<usize>) -> Self::SerializeSeq;
+        serialize_tuple(len: usize) -> Self::SerializeTuple;
+        serialize_tuple_struct(name: &'static str, len: usize) -> Self::SerializeTupleStruct;
+        serialize_tuple_variant(
+            name: &'static str,
+            index: u32,
+            variant: &'static str,
+            len: usize
+        ) -> Self::SerializeTupleVariant;
+        serialize_map(len: Option<usize>) -> Self::SerializeMap;
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<(), Error> {
+        self.0.serialize_some(value)
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.0.serialize_newtype_struct(name, value)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.0
+            .serialize_newtype_variant(name, index, variant, value)
+    }
+
+    fn collect_seq<I>(self, iter: I) -> Result<(), Error>
+    where
+        I: IntoIterator,
+        I::Item: Serialize,
+    {
+        self.0.collect_seq(iter)
+    }
+
+    fn collect_map<K, V, I>(self, iter: I) -> Result<(), Error>
+    where
+        K: Serialize,
+        V: Serialize,
+        I: IntoIterator<Item = (K, V)>,
+    {
+        self.0.collect_map(iter)
+    }
+
+    fn collect_str<T: ?Sized + std::fmt::Display>(self, value: &T) -> Result<(), Error> {
+        self.0.collect_str(value)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        ser::Serializer::is_human_readable(&self.0)
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<Fields<'a, W, C>, Error> {
+        rmp::encode::write_array_len(self.0.get_mut(), len as u32)?;
+        Ok(Fields(self.0))
+    }
+
+    /// The same `{variant: fields}` as rmp_serde but with the fields as an array.
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Fields<'a, W, C>, Error> {
+        rmp::encode::write_map_len(self.0.get_mut(), 1)?;
+        rmp::encode::write_str(self.0.get_mut(), variant)?;
+        rmp::encode::write_array_len(self.0.get_mut(), len as u32)?;
+        Ok(Fields(self.0))
+    }
+}
+
+/// The field values of a compact struct, in order and without their names.
+pub(crate) struct Fields<'a, W, C>(Inner<'a, W, C>);
+
+impl<W: Write, C: SerializerConfig> ser::SerializeStruct for Fields<'_, W, C> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        value.serialize(&mut *self.0)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<W: Write, C: SerializerConfig> ser::SerializeStructVariant for Fields<'_, W, C> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        value.serialize(&mut *self.0)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
@@ -0,0 +1,274 @@
+use crate::msgpack::{read_token, skip, Kind, ReadError};
+use crate::proxy;
+use serde::Deserialize;
+use serde_path_to_error::{Path, Segment, Track};
+
+/// Broadly what went wrong while deserializing.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+pub enum DeserializeErrorKind {
+    /// the bytes hold a different type of value than the target type expects
+    TypeMismatch,
+    /// the input ends part way through a value
+    UnexpectedEof,
+    /// a declared length doesn't fit in the input or the target type
+    LengthOverflow,
+    /// a sequence has a different number of items than the target type expects
+    LengthMismatch,
+    /// a str is not valid UTF-8
+    InvalidUtf8,
+    /// a marker byte that messagepack reserves and never uses
+    InvalidMarker,
+    /// an enum variant the target type doesn't have
+    UnknownVariant,
+    /// a struct field the target type doesn't have
+    UnknownField,
+    /// a struct field the target type needs is absent
+    MissingField,
+    /// a struct field appears more than once
+    DuplicateField,
+    /// the value has the right type but is not acceptable to the target type
+    InvalidValue,
+    /// values are nested too deeply
+    DepthExceeded,
+    /// there are more bytes after the end of the value
+    TrailingBytes,
+    /// a `Versioned` envelope is from a version there is no migration from
+    UnsupportedVersion,
+    /// anything else, e.g. a custom error from a hand written `Deserialize` implementation
+    Custom,
+}
+
+/// Everything we know about a failure to deserialize.
+///
+/// Everything is owned and (de)serializable so the error can cross the wasm boundary intact.
+/// Errors from versions before this struct existed, which were only a message, deserialize too.
+#[derive(
+    Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+#[serde(from = "DeserializeErrorWire")]
+pub struct DeserializeError {
+    pub kind: DeserializeErrorKind,
+    /// offset of the value that couldn't be deserialized, if it is known
+    pub offset: Option<usize>,
+    /// `std::any::type_name` of the type being deserialized into, if it is known
+    pub type_name: String,
+    /// serde path to the field that couldn't be deserialized e.g. `entry.header.author`
+    /// empty if the error is with the outermost value
+    pub path: String,
+    pub message: String,
+}
+
+/// Everything `DeserializeError` has been serialized as.
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum DeserializeErrorWire {
+    Error {
+        kind: DeserializeErrorKind,
+        offset: Option<usize>,
+        type_name: String,
+        path: String,
+        message: String,
+    },
+    Message(String),
+}
+
+impl From<DeserializeErrorWire> for DeserializeError {
+    fn from(wire: DeserializeErrorWire) -> Self {
+        match wire {
+            DeserializeErrorWire::Error {
+                kind,
+                offset,
+                type_name,
+                path,
+                message,
+            } => Self {
+                kind,
+                offset,
+                type_name,
+                path,
+                message,
+            },
+            DeserializeErrorWire::Message(message) => message.into(),
+        }
+    }
+}
+
+impl DeserializeError {
+    pub(crate) fn from_read(err: ReadError, type_name: &str) -> Self {
+        let (kind, offset) = match err {
+            ReadError::UnexpectedEof { offset } => (DeserializeErrorKind::UnexpectedEof, offset),
+            ReadError::ReservedMarker { offset } => (DeserializeErrorKind::InvalidMarker, offset),
+            ReadError::LengthOverflow { offset, .. } => {
+                (DeserializeErrorKind::LengthOverflow, offset)
+            }
+        };
+        Self {
+            kind,
+            offset: Some(offset),
+            type_name: type_name.to_string(),
+            path: String::new(),
+            message: err.to_string(),
+        }
+    }
+
+    /// Build the error from what the deserializer reported, the path serde got to and the offset
+    /// the path leads to.
+    pub(crate) fn from_rmp(
+        err: &proxy::Error<rmp_serde::decode::Error>,
+        path: &Path,
+        offset: usize,
+        type_name: &str,
+    ) -> Self {
+        use rmp_serde::decode::Error;
+        let kind = match err {
+            proxy::Error::Raised(kind, _) => *kind,
+            proxy::Error::Inner(err) => match err {
+                Error::InvalidMarkerRead(err) | Error::InvalidDataRead(err)
+                    if err.kind() == std::io::ErrorKind::UnexpectedEof =>
+                {
+                    DeserializeErrorKind::UnexpectedEof
+                }
+                Error::InvalidMarkerRead(_) | Error::InvalidDataRead(_) => {
+                    DeserializeErrorKind::Custom
+                }
+                Error::TypeMismatch(rmp::Marker::Reserved) => DeserializeErrorKind::InvalidMarker,
+                Error::TypeMismatch(_) => DeserializeErrorKind::TypeMismatch,
+                Error::OutOfRange => DeserializeErrorKind::LengthOverflow,
+                Error::LengthMismatch(_) => DeserializeErrorKind::LengthMismatch,
+                Error::Utf8Error(_) => DeserializeErrorKind::InvalidUtf8,
+                Error::DepthLimitExceeded => DeserializeErrorKind::DepthExceeded,
+                Error::Uncategorized(_) | Error::Syntax(_) => DeserializeErrorKind::Custom,
+            },
+        };
+        Self {
+            kind,
+            offset: Some(offset),
+            type_name: type_name.to_string(),
+            path: if path.iter().next().is_some() {
+                path.to_string()
+            } else {
+                String::new()
+            },
+            message: err.to_string(),
+        }
+    }
+}
+
+/// Explain why deserializing `bytes` into `T` failed with `err`.
+///
+/// Tracking the serde path and the kind of error slows down every deserialization so instead the
+/// failed deserialization is repeated with tracking once we know there is an error to explain.
+pub(crate) fn explain<'a, T: Deserialize<'a>>(
+    bytes: &'a [u8],
+    err: rmp_serde::decode::Error,
+) -> DeserializeError {
+    let mut deserializer = rmp_serde::Deserializer::from_read_ref(bytes);
+    match deserialize_tracked::<T>(bytes, &mut deserializer, &proxy::State::default()) {
+        Err(err) => err,
+        Ok(_) => DeserializeError::from_rmp(
+            &proxy::Error::Inner(err),
+            &Track::new().path(),
+            0,
+            std::any::type_name::<T>(),
+        ),
+    }
+}
+
+/// Deserialize the value at the start of `bytes`, keeping track of everything needed to explain
+/// an error. `deserializer` is left at the end of the value.
+pub(crate) fn deserialize_tracked<'a, T: Deserialize<'a>>(
+    bytes: &'a [u8],
+    deserializer: &mut rmp_serde::Deserializer<rmp_serde::decode::ReadRefReader<'a, [u8]>>,
+    state: &proxy::State,
+) -> Result<T, DeserializeError> {
+    let mut track = Track::new();
+    T::deserialize(serde_path_to_error::Deserializer::new(
+        proxy::Deserializer::new(deserializer, state),
+        &mut track,
+    ))
+    .map_err(|err| {
+        let path = track.path();
+        DeserializeError::from_rmp(
+            &err,
+            &path,
+            locate(bytes, &path),
+            std::any::type_name::<T>(),
+        )
+    })
+}
+
+/// Follow a serde path through the bytes as far as possible and return the offset it ends at.
+///
+/// This can't follow a path through a struct encoded as an array (the field names aren't in the
+/// bytes) so the offset is then the start of the array.
+pub(crate) fn locate(bytes: &[u8], path: &Path) -> usize {
+    let mut pos = 0;
+    for segment in path {
+        let Ok(token) = read_token(bytes, pos) else {
+            break;
+        };
+        let next = match (segment, token.kind) {
+            (Segment::Seq { index }, Kind::Array(len)) if *index < len => {
+                (0..*index).try_fold(token.end(), |pos, _| skip(bytes, pos).ok())
+            }
+            (Segment::Map { key }, Kind::Map(len))
+            | (Segment::Enum { variant: key }, Kind::Map(len)) => {
+                map_value(bytes, token.end(), len, key)
+            }
+            _ => None,
+        };
+        match next {
+            Some(next) => pos = next,
+            None => break,
+        }
+    }
+    pos
+}
+
+/// Offset of the value for the str `key` in the map entries starting at `pos`.
+fn map_value(bytes: &[u8], mut pos: usize, len: usize, key: &str) -> Option<usize> {
+    for _ in 0..len {
+        let token = read_token(bytes, pos).ok()?;
+        if token.kind == Kind::Str(key.len()) && token.payload(bytes) == key.as_bytes() {
+            return Some(token.end());
+        }
+        pos = skip(bytes, skip(bytes, pos).ok()?).ok()?;
+    }
+    None
+}
+
+impl From<String> for DeserializeError {
+    fn from(message: String) -> Self {
+        Self {
+            kind: DeserializeErrorKind::Custom,
+            offset: None,
+            type_name: String::new(),
+            path: String::new(),
+            message,
+        }
+    }
+}
+
+impl From<&str> for DeserializeError {
+    fn from(message: &str) -> Self {
+        message.to_string().into()
+    }
+}
+
+impl std::fmt::Display for DeserializeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "failed to deserialize")?;
+        if !self.type_name.is_empty() {
+            write!(f, " {}", self.type_name)?;
+        }
+        if !self.path.is_empty() {
+            write!(f, " at `{}`", self.path)?;
+        }
+        if let Some(offset) = self.offset {
+            write!(f, " (byte {})", offset)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
@@ -0,0 +1,295 @@
+//! Length prefixed frames of `SerializedBytes` for streams, e.g. TCP, QUIC or unix sockets.
+//!
+//! A frame is a length prefix followed by exactly that many bytes of a `SerializedBytes`.
+//! The frame bytes are never interpreted, decode them with `TryFrom` as usual once the frame is
+//! complete.
+
+use crate::BytesMut;
+use crate::SerializedBytes;
+use crate::SerializedBytesError;
+use crate::UnsafeBytes;
+use std::io::{Read, Write};
+
+/// How the length of each frame is written in front of it.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+pub enum LengthPrefix {
+    /// 4 byte big endian (network order) unsigned integer
+    U32,
+    /// unsigned LEB128 varint, 1 byte for frames under 128 bytes and at most 10 bytes
+    Varint,
+}
+
+impl LengthPrefix {
+    /// The most bytes a prefix of this kind can take.
+    pub fn max_len(&self) -> usize {
+        match self {
+            LengthPrefix::U32 => 4,
+            LengthPrefix::Varint => 10,
+        }
+    }
+}
+
+/// Something wrong with a frame rather than the bytes in it.
+#[derive(
+    Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+pub enum FrameError {
+    /// the frame is, or declares that it is, longer than the max frame size
+    TooLarge { len: u64, max: usize },
+    /// the stream ended part way through a frame
+    Truncated { expected: usize, got: usize },
+    /// a varint prefix is longer than 10 bytes, overflows a u64 or is longer than it needs to be
+    InvalidPrefix,
+    /// reading or writing the stream failed
+    Io(String),
+}
+
+impl std::fmt::Display for FrameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FrameError::TooLarge { len, max } => write!(
+                f,
+                "frame of {} bytes is larger than the max frame size of {}",
+                len, max
+            ),
+            FrameError::Truncated { expected, got } => write!(
+                f,
+                "stream ended after {} of the {} bytes of a frame",
+                got, expected
+            ),
+            FrameError::InvalidPrefix => write!(f, "invalid varint frame length prefix"),
+            FrameError::Io(s) => write!(f, "frame io failed: {}", s),
+        }
+    }
+}
+
+impl From<FrameError> for SerializedBytesError {
+    fn from(err: FrameError) -> Self {
+        #[cfg(feature = "trace")]
+        tracing::warn!("Frame error: {}", err);
+        SerializedBytesError::Frame(err)
+    }
+}
+
+fn io(err: std::io::Error) -> FrameError {
+    FrameError::Io(err.to_string())
+}
+
+/// The result of trying to decode a frame from the bytes received so far.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FrameStatus {
+    /// A whole frame, and the number of bytes it took up including the prefix.
+    /// Those bytes have been split off the front of the buffer.
+    Complete { frame: SerializedBytes, len: usize },
+    /// Not enough bytes for a whole frame yet. At least `needed` more bytes are required.
+    /// Nothing was consumed, call again once more bytes have been appended to the buffer.
+    Incomplete { needed: usize },
+}
+
+/// How frames are written and read.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+pub struct Framing {
+    pub prefix: LengthPrefix,
+    /// frames longer than this, not counting the prefix, are refused on both write and read
+    /// a declared length is checked before anything is buffered for it
+    pub max_frame_size: usize,
+}
+
+/// u32 prefixes with a max frame size of 16MiB.
+impl Default for Framing {
+    fn default() -> Self {
+        Framing {
+            prefix: LengthPrefix::U32,
+            max_frame_size: 16 << 20,
+        }
+    }
+}
+
+impl Framing {
+    /// Append the frame for `sb` to `buf`.
+    pub fn encode_frame(
+        &self,
+        sb: &SerializedBytes,
+        buf: &mut Vec<u8>,
+    ) -> Result<(), SerializedBytesError> {
+        let bytes = sb.bytes();
+        self.write_prefix(bytes.len(), buf)?;
+        buf.extend_from_slice(bytes);
+        Ok(())
+    }
+
+    /// Write the frame for `sb` to `writer`.
+    pub fn write_frame<W: Write>(
+        &self,
+        sb: &SerializedBytes,
+        mut writer: W,
+    ) -> Result<(), SerializedBytesError> {
+        let mut prefix = Vec::with_capacity(self.prefix.max_len());
+        self.write_prefix(sb.bytes().len(), &mut prefix)?;
+        writer
+            .write_all(&prefix)
+            .and_then(|_| writer.write_all(sb.bytes()))
+            .map_err(io)?;
+        Ok(())
+    }
+
+    /// Split the frame off the start of `buf`, if it has all arrived.
+    ///
+    /// This never blocks or waits, so it suits non-blocking reads into a buffer. The frame
+    /// shares the memory of `buf` rather than being copied out of it.
+    pub fn decode_frame(&self, buf: &mut BytesMut) -> Result<FrameStatus, SerializedBytesError> {
+        Ok(match self.read_prefix(buf)? {
+            Err(needed) => FrameStatus::Incomplete { needed },
+            Ok((prefix_len, frame_len)) => match buf.len() - prefix_len {
+                got if got < frame_len => FrameStatus::Incomplete {
+                    needed: frame_len - got,
+                },
+                _ => {
+                    let frame = buf.split_to(prefix_len + frame_len).split_off(prefix_len);
+                    FrameStatus::Complete {
+                        frame: SerializedBytes::from(UnsafeBytes::from(frame.freeze())),
+                        len: prefix_len + frame_len,
+                    }
+                }
+            },
+        })
+    }
+
+    /// Read one whole frame from `reader`, blocking until it has all arrived.
+    ///
+    /// Returns `None` if the stream ends cleanly between frames, and `FrameError::Truncated` if
+    /// it ends part way through one.
+    pub fn read_frame<R: Read>(
+        &self,
+        mut reader: R,
+    ) -> Result<Option<SerializedBytes>, SerializedBytesError> {
+        let mut prefix = Vec::with_capacity(self.prefix.max_len());
+        let frame_len = loop {
+            let needed = match self.read_prefix(&prefix)? {
+                Ok((_, frame_len)) => break frame_len,
+                Err(needed) => needed,
+            };
+            let start = prefix.len();
+            prefix.resize(start + needed, 0);
+            let got = read_fully(&mut reader, &mut prefix[start..])?;
+            if got < needed {
+                if start + got == 0 {
+                    return Ok(None);
+                }
+                return Err(FrameError::Truncated {
+                    expected: start + needed,
+                    got: start + got,
+                }
+                .into());
+            }
+        };
+        let mut frame = vec![0; frame_len];
+        let got = read_fully(&mut reader, &mut frame)?;
+        if got < frame_len {
+            return Err(FrameError::Truncated {
+                expected: prefix.len() + frame_len,
+                got: prefix.len() + got,
+            }
+            .into());
+        }
+        Ok(Some(SerializedBytes::from(UnsafeBytes::from(frame))))
+    }
+
+    pub(crate) fn write_prefix(&self, len: usize, buf: &mut Vec<u8>) -> Result<(), FrameError> {
+        if len > self.max_frame_size {
+            return Err(FrameError::TooLarge {
+                len: len as u64,
+                max: self.max_frame_size,
+            });
+        }
+        match self.prefix {
+            LengthPrefix::U32 => {
+                let len = u32::try_from(len).map_err(|_| FrameError::TooLarge {
+                    len: len as u64,
+                    max: u32::MAX as usize,
+                })?;
+                buf.extend_from_slice(&len.to_be_bytes());
+            }
+            LengthPrefix::Varint => {
+                let mut len = len as u64;
+                while len >= 0x80 {
+                    buf.push(len as u8 | 0x80);
+                    len >>= 7;
+                }
+                buf.push(len as u8);
+            }
+        }
+        Ok(())
+    }
+
+    /// Read the prefix at the start of `buf` and check it against the max frame size.
+    ///
+    /// `Ok(Ok((prefix_len, frame_len)))` for a complete prefix, otherwise `Ok(Err(needed))` with
+    /// the fewest bytes still needed to complete it.
+    pub(crate) fn read_prefix(
+        &self,
+        buf: &[u8],
+    ) -> Result<Result<(usize, usize), usize>, FrameError> {
+        let (prefix_len, len) = match self.prefix {
+            LengthPrefix::U32 => match buf.get(..4) {
+                Some(prefix) => (
+                    4,
+                    u64::from(u32::from_be_bytes([
+                        prefix[0], prefix[1], prefix[2], prefix[3],
+                    ])),
+                ),
+                None => return Ok(Err(4 - buf.len())),
+            },
+            LengthPrefix::Varint => {
+                let mut len: u64 = 0;
+                let mut prefix_len = None;
+                for (i, byte) in buf.iter().take(10).enumerate() {
+                    let bits = u64::from(byte & 0x7f);
+                    if i == 9 && bits > 1 {
+                        return Err(FrameError::InvalidPrefix);
+                    }
+                    len |= bits << (7 * i);
+                    if byte & 0x80 == 0 {
+                        // a trailing zero byte adds nothing, so the same length has a shorter
+                        // prefix and a frame would have more than one encoding
+                        if i > 0 && bits == 0 {
+                            return Err(FrameError::InvalidPrefix);
+                        }
+                        prefix_len = Some(i + 1);
+                        break;
+                    }
+                }
+                match prefix_len {
+                    Some(prefix_len) => (prefix_len, len),
+                    None if buf.len() >= 10 => return Err(FrameError::InvalidPrefix),
+                    None => return Ok(Err(1)),
+                }
+            }
+        };
+        match usize::try_from(len) {
+            Ok(frame_len) if frame_len <= self.max_frame_size => Ok(Ok((prefix_len, frame_len))),
+            _ => Err(FrameError::TooLarge {
+                len,
+                max: self.max_frame_size,
+            }),
+        }
+    }
+}
+
+/// Like `read_exact` but returns how much was read if the stream ends early.
+fn read_fully<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<usize, FrameError> {
+    let mut got = 0;
+    while got < buf.len() {
+        match reader.read(&mut buf[got..]) {
+            Ok(0) => break,
+            Ok(n) => got += n,
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(io(err)),
+        }
+    }
+    Ok(got)
+}
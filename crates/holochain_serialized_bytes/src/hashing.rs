@@ -0,0 +1,108 @@
+//! Content hashes of serialized bytes, e.g. for DHT addressing.
+//!
+//! A content hash is always the hash of the exact bytes `holochain_serial!` produces for a value,
+//! never of some other serialization of it.
+
+use crate::SerializedBytes;
+use crate::SerializedBytesError;
+use blake2::digest::consts::U32;
+use blake2::Digest;
+
+/// A 32 byte hash function over bytes.
+pub trait HashAlgorithm {
+    fn hash(bytes: &[u8]) -> [u8; 32];
+}
+
+/// Blake2b with a 256 bit output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Blake2b256;
+
+impl HashAlgorithm for Blake2b256 {
+    fn hash(bytes: &[u8]) -> [u8; 32] {
+        blake2::Blake2b::<U32>::digest(bytes).into()
+    }
+}
+
+/// SHA-256.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Sha256;
+
+impl HashAlgorithm for Sha256 {
+    fn hash(bytes: &[u8]) -> [u8; 32] {
+        sha2::Sha256::digest(bytes).into()
+    }
+}
+
+impl SerializedBytes {
+    pub fn hash_blake2b_256(&self) -> [u8; 32] {
+        Blake2b256::hash(self.bytes())
+    }
+
+    pub fn sha256(&self) -> [u8; 32] {
+        Sha256::hash(self.bytes())
+    }
+}
+
+/// The hash of the `SerializedBytes` of a value.
+///
+/// Implemented for every type with `holochain_serial!` impls, and for `SerializedBytes` itself.
+pub trait ContentHash {
+    fn content_hash<H: HashAlgorithm>(&self) -> Result<[u8; 32], SerializedBytesError>;
+}
+
+impl<T> ContentHash for T
+where
+    for<'a> SerializedBytes: TryFrom<&'a T, Error = SerializedBytesError>,
+{
+    fn content_hash<H: HashAlgorithm>(&self) -> Result<[u8; 32], SerializedBytesError> {
+        SerializedBytes::try_from(self).map(|sb| H::hash(sb.bytes()))
+    }
+}
+
+/// A value along with its content hash, so the value is only serialized and hashed once.
+///
+/// The value can't be changed without hashing it again, so the hash is always the hash of what
+/// `holochain_serial!` produces for the value.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Hashed<T, H = Blake2b256> {
+    content: T,
+    hash: [u8; 32],
+    algorithm: std::marker::PhantomData<H>,
+}
+
+impl<T: ContentHash, H: HashAlgorithm> Hashed<T, H> {
+    pub fn new(content: T) -> Result<Self, SerializedBytesError> {
+        let hash = content.content_hash::<H>()?;
+        Ok(Self {
+            content,
+            hash,
+            algorithm: std::marker::PhantomData,
+        })
+    }
+}
+
+impl<T, H> Hashed<T, H> {
+    pub fn content(&self) -> &T {
+        &self.content
+    }
+
+    pub fn hash(&self) -> &[u8; 32] {
+        &self.hash
+    }
+
+    pub fn into_content(self) -> T {
+        self.content
+    }
+
+    pub fn into_parts(self) -> (T, [u8; 32]) {
+        (self.content, self.hash)
+    }
+}
+
+impl<T, H> std::ops::Deref for Hashed<T, H> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.content
+    }
+}
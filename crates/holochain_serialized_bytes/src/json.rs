@@ -0,0 +1,420 @@
+//! JSON for clients that can't read messagepack, e.g. JS in a browser.
+//!
+//! Unlike the `Debug` rendering this is a two way conversion, everything messagepack can hold
+//! has a JSON form that converts back to the same messagepack, see `JsonOptions`.
+
+use crate::msgpack::{write_head, Kind};
+use crate::value::{self, Value};
+use crate::DeserializeError;
+use crate::DeserializeErrorKind;
+use crate::SerializedBytesError;
+use base64::Engine;
+use serde_json::Value as Json;
+
+/// The largest integer magnitude a JS number holds exactly, `Number.MAX_SAFE_INTEGER`.
+const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+
+/// Keys of the single entry objects that stand in for values plain JSON can't hold.
+const TAGS: [&str; 6] = ["$bin", "$ext", "$f32", "$f64", "$int", "$map"];
+
+/// How bin payloads are written as JSON.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum JsonBin {
+    /// `{"$bin": "<base64>"}`, which converts back to a bin.
+    Tagged,
+    /// A plain base64 string, which is simpler for clients but converts back to a str.
+    Base64,
+}
+
+/// How `SerializedBytes::to_json` writes the bytes as JSON.
+///
+/// Plain JSON can't hold everything messagepack can, so a few values are written as objects with
+/// a single `$` key instead:
+///
+/// - bin is `{"$bin": "<base64>"}`, unless `bin` is `JsonBin::Base64`
+/// - ext is `{"$ext": [<type>, "<base64>"]}`
+/// - f32 is `{"$f32": <number>}`
+/// - NaN and infinite floats are `{"$f64": "NaN"}`, `"Infinity"` or `"-Infinity"`, or the same
+///   inside `$f32`
+/// - NaNs other than `f64::NAN` or `f32::NAN`, e.g. `-f64::NAN`, are their bits in hex, e.g.
+///   `{"$f64": "0xfff8000000000000"}` or `{"$f32": "0xffc00000"}`
+/// - integers beyond ±(2^53 - 1), which JS would round, are `{"$int": "<decimal>"}`, unless
+///   `safe_integers` is false
+/// - maps that can't be objects are `{"$map": [[<key>, <value>], ...]}`, i.e. maps with keys
+///   that aren't strings, with a key more than once, or with a single key that is one of these
+///
+/// Everything else is the obvious JSON, keeping the order of map entries. Base64 is standard and
+/// padded. Numbers with a fraction or exponent are f64 and any other number is an integer.
+///
+/// `SerializedBytes::from_json_value` reverses all of this, so with `JsonOptions::LOSSLESS`
+/// bytes that use the smallest marker for every value, as `encode` always does, convert back
+/// byte for byte.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct JsonOptions {
+    pub bin: JsonBin,
+    /// write integers beyond ±(2^53 - 1) as `{"$int": "<decimal>"}` rather than numbers
+    pub safe_integers: bool,
+}
+
+impl JsonOptions {
+    /// Everything converts back from JSON exactly as it was, and JS reads every integer exactly.
+    pub const LOSSLESS: JsonOptions = JsonOptions {
+        bin: JsonBin::Tagged,
+        safe_integers: true,
+    };
+}
+
+impl Default for JsonOptions {
+    fn default() -> Self {
+        Self::LOSSLESS
+    }
+}
+
+/// The complete messagepack value in `bytes` as JSON.
+pub(crate) fn to_json(bytes: &[u8], options: &JsonOptions) -> Result<Json, SerializedBytesError> {
+    Ok(json(value::to_value(bytes)?, options))
+}
+
+/// What is left to do converting a `Value` to JSON.
+enum Convert {
+    Value(Value),
+    /// Collect this many converted items into an array.
+    Array(usize),
+    /// Collect this many converted keys and values into an object.
+    Object(usize),
+    /// Collect this many converted keys and values into a `$map`.
+    Pairs(usize),
+}
+
+/// Converts without recursing so that anything `decode` accepts converts, however deep.
+fn json(value: Value, options: &JsonOptions) -> Json {
+    let unsafe_integer = |n: u64| options.safe_integers && n > MAX_SAFE_INTEGER;
+    let mut todo = vec![Convert::Value(value)];
+    let mut done = Vec::new();
+    while let Some(next) = todo.pop() {
+        let json = match next {
+            Convert::Value(Value::Array(items)) => {
+                todo.push(Convert::Array(items.len()));
+                todo.extend(items.into_iter().rev().map(Convert::Value));
+                continue;
+            }
+            Convert::Value(Value::Map(entries)) => {
+                todo.push(match is_object(&entries) {
+                    true => Convert::Object(entries.len()),
+                    false => Convert::Pairs(entries.len()),
+                });
+                for (k, v) in entries.into_iter().rev() {
+                    todo.push(Convert::Value(v));
+                    todo.push(Convert::Value(k));
+                }
+                continue;
+            }
+            Convert::Value(value) => match value {
+                Value::Nil => Json::Null,
+                Value::Bool(b) => Json::Bool(b),
+                Value::UInt(n) if unsafe_integer(n) => tagged("$int", Json::String(n.to_string())),
+                Value::UInt(n) => n.into(),
+                Value::Int(n) if unsafe_integer(n.unsigned_abs()) => {
+                    tagged("$int", Json::String(n.to_string()))
+                }
+                Value::Int(n) => n.into(),
+                Value::F32(n) if n.is_nan() && n.to_bits() != f32::NAN.to_bits() => {
+                    tagged("$f32", Json::String(format!("0x{:08x}", n.to_bits())))
+                }
+                Value::F32(n) => tagged("$f32", float(n.into())),
+                Value::F64(n) if n.is_finite() => float(n),
+                Value::F64(n) if n.is_nan() && n.to_bits() != f64::NAN.to_bits() => {
+                    tagged("$f64", Json::String(format!("0x{:016x}", n.to_bits())))
+                }
+                Value::F64(n) => tagged("$f64", float(n)),
+                Value::Str(s) => Json::String(s),
+                Value::Bin(b) => match options.bin {
+                    JsonBin::Tagged => tagged("$bin", base64(&b)),
+                    JsonBin::Base64 => base64(&b),
+                },
+                Value::Ext(ty, data) => tagged("$ext", Json::Array(vec![ty.into(), base64(&data)])),
+                Value::Array(_) | Value::Map(_) => unreachable!("containers are converted above"),
+            },
+            Convert::Array(len) => Json::Array(done.split_off(done.len() - len)),
+            Convert::Object(len) => Json::Object(
+                pairs(done.split_off(done.len() - 2 * len))
+                    .filter_map(|(k, v)| match k {
+                        Json::String(k) => Some((k, v)),
+                        _ => None,
+                    })
+                    .collect(),
+            ),
+            Convert::Pairs(len) => tagged(
+                "$map",
+                Json::Array(
+                    pairs(done.split_off(done.len() - 2 * len))
+                        .map(|(k, v)| Json::Array(vec![k, v]))
+                        .collect(),
+                ),
+            ),
+        };
+        done.push(json);
+    }
+    done.pop().expect("a value converts to a single JSON value")
+}
+
+/// Alternating keys and values as pairs.
+fn pairs(items: Vec<Json>) -> impl Iterator<Item = (Json, Json)> {
+    let mut items = items.into_iter();
+    std::iter::from_fn(move || Some((items.next()?, items.next()?)))
+}
+
+fn tagged(tag: &str, content: Json) -> Json {
+    Json::Object([(tag.to_string(), content)].into_iter().collect())
+}
+
+fn base64(bytes: &[u8]) -> Json {
+    Json::String(base64::engine::general_purpose::STANDARD.encode(bytes))
+}
+
+/// A number if the float is finite, otherwise its name.
+fn float(n: f64) -> Json {
+    match serde_json::Number::from_f64(n) {
+        Some(n) => Json::Number(n),
+        None if n.is_nan() => Json::String("NaN".into()),
+        None if n > 0.0 => Json::String("Infinity".into()),
+        None => Json::String("-Infinity".into()),
+    }
+}
+
+/// Whether the map entries can be written as a JSON object and still convert back to the same
+/// map.
+fn is_object(entries: &[(Value, Value)]) -> bool {
+    let mut keys = std::collections::HashSet::new();
+    let unique_strs = entries
+        .iter()
+        .all(|(k, _)| matches!(k, Value::Str(k) if keys.insert(k.as_str())));
+    let tag = match entries {
+        [(Value::Str(k), _)] => TAGS.contains(&k.as_str()),
+        _ => false,
+    };
+    unique_strs && !tag
+}
+
+/// The messagepack for JSON following the conventions of `JsonOptions`.
+pub(crate) fn from_json(json: &Json) -> Result<Vec<u8>, SerializedBytesError> {
+    let mut out = Vec::new();
+    let mut path = Vec::new();
+    write(&mut out, json, &mut path).map_err(|(kind, message)| {
+        SerializedBytesError::Deserialize(DeserializeError {
+            kind,
+            offset: None,
+            type_name: std::any::type_name::<Json>().to_string(),
+            path: path.join("."),
+            message,
+        })
+    })?;
+    Ok(out)
+}
+
+/// What is wrong with the JSON at the path left behind by `write`.
+type JsonError = (DeserializeErrorKind, String);
+
+/// What is left to do writing JSON as messagepack.
+enum Write<'j> {
+    /// Write the value, entering the path segment while it and its contents are written.
+    Value(&'j Json, Option<String>),
+    /// Write an object key.
+    Key(&'j str),
+    /// Write the `[key, value]` entry of a `$map` at this index.
+    Entry(&'j Json, usize),
+    /// Leave the last path segment.
+    Leave,
+}
+
+/// Writes the messagepack for `json`, leaving `path` at the value that is wrong on error.
+///
+/// This doesn't recurse so that JSON nested as deep as `to_json` writes converts back.
+fn write(out: &mut Vec<u8>, json: &Json, path: &mut Vec<String>) -> Result<(), JsonError> {
+    let mut todo = vec![Write::Value(json, None)];
+    while let Some(next) = todo.pop() {
+        let json = match next {
+            Write::Value(json, segment) => {
+                if let Some(segment) = segment {
+                    path.push(segment);
+                    todo.push(Write::Leave);
+                }
+                json
+            }
+            Write::Key(key) => {
+                write_head(out, Kind::Str(len(key.len())?));
+                out.extend_from_slice(key.as_bytes());
+                continue;
+            }
+            Write::Entry(entry, i) => {
+                path.push(i.to_string());
+                todo.push(Write::Leave);
+                match entry.as_array().map(Vec::as_slice) {
+                    Some([key, value]) => {
+                        todo.push(Write::Value(value, Some("1".into())));
+                        todo.push(Write::Value(key, Some("0".into())));
+                    }
+                    _ => return Err(invalid("a key and value", entry)),
+                }
+                continue;
+            }
+            Write::Leave => {
+                path.pop();
+                continue;
+            }
+        };
+        match json {
+            Json::Null => write_head(out, Kind::Nil),
+            Json::Bool(b) => write_head(out, Kind::Bool(*b)),
+            Json::Number(n) => write_head(out, number(n)),
+            Json::String(s) => {
+                write_head(out, Kind::Str(len(s.len())?));
+                out.extend_from_slice(s.as_bytes());
+            }
+            Json::Array(items) => {
+                write_head(out, Kind::Array(len(items.len())?));
+                let items = items.iter().enumerate().rev();
+                todo.extend(items.map(|(i, item)| Write::Value(item, Some(i.to_string()))));
+            }
+            Json::Object(object) => match object.iter().next() {
+                Some((tag, content)) if object.len() == 1 && TAGS.contains(&tag.as_str()) => {
+                    path.push(tag.clone());
+                    todo.push(Write::Leave);
+                    write_tagged(out, tag, content, &mut todo)?;
+                }
+                _ => {
+                    write_head(out, Kind::Map(len(object.len())?));
+                    for (key, value) in object.iter().rev() {
+                        todo.push(Write::Value(value, Some(key.clone())));
+                        todo.push(Write::Key(key));
+                    }
+                }
+            },
+        }
+    }
+    Ok(())
+}
+
+/// Writes the value of a single `$` key object, leaving the entries of a `$map` to `todo`.
+fn write_tagged<'j>(
+    out: &mut Vec<u8>,
+    tag: &str,
+    content: &'j Json,
+    todo: &mut Vec<Write<'j>>,
+) -> Result<(), JsonError> {
+    match (tag, content) {
+        ("$bin", Json::String(b64)) => {
+            let bytes = from_base64(b64)?;
+            write_head(out, Kind::Bin(len(bytes.len())?));
+            out.extend_from_slice(&bytes);
+        }
+        ("$ext", Json::Array(ext)) => match ext.as_slice() {
+            [ty, Json::String(b64)] => {
+                let ty = ty
+                    .as_i64()
+                    .and_then(|ty| i8::try_from(ty).ok())
+                    .ok_or_else(|| invalid("an ext type from -128 to 127", ty))?;
+                let data = from_base64(b64)?;
+                write_head(out, Kind::Ext(ty, len(data.len())?));
+                out.extend_from_slice(&data);
+            }
+            _ => return Err(invalid("an ext type and base64 data", content)),
+        },
+        ("$f32", content) => {
+            let n = match from_bits(content, 8) {
+                Some(bits) => f32::from_bits(bits as u32),
+                None => from_float(content)? as f32,
+            };
+            write_head(out, Kind::F32(n));
+        }
+        ("$f64", content) => {
+            let n = match from_bits(content, 16) {
+                Some(bits) => f64::from_bits(bits),
+                None => from_float(content)?,
+            };
+            write_head(out, Kind::F64(n));
+        }
+        ("$int", Json::String(n)) => {
+            let kind = match (n.parse::<u64>(), n.parse::<i64>()) {
+                (Ok(n), _) => Kind::UInt(n),
+                (_, Ok(n)) => Kind::Int(n),
+                _ => return Err(invalid("a 64 bit integer", content)),
+            };
+            write_head(out, kind);
+        }
+        ("$map", Json::Array(entries)) => {
+            write_head(out, Kind::Map(len(entries.len())?));
+            todo.extend(
+                entries
+                    .iter()
+                    .enumerate()
+                    .rev()
+                    .map(|(i, entry)| Write::Entry(entry, i)),
+            );
+        }
+        ("$bin", _) => return Err(invalid("base64", content)),
+        ("$int", _) => return Err(invalid("a decimal string", content)),
+        ("$ext", _) | ("$map", _) => return Err(invalid("an array", content)),
+        _ => unreachable!("only tags are written as tagged values"),
+    }
+    Ok(())
+}
+
+/// Integers are the smallest int that holds them, exactly as rmp_serde writes them, and
+/// anything else is an f64.
+fn number(n: &serde_json::Number) -> Kind {
+    match (n.as_u64(), n.as_i64()) {
+        (Some(n), _) => Kind::UInt(n),
+        (_, Some(n)) => Kind::Int(n),
+        _ => Kind::F64(n.as_f64().unwrap_or(f64::NAN)),
+    }
+}
+
+fn from_float(content: &Json) -> Result<f64, JsonError> {
+    const EXPECTED: &str = "a number, \"NaN\", \"Infinity\", \"-Infinity\" or the bits in hex";
+    match content {
+        Json::Number(n) => n.as_f64().ok_or_else(|| invalid(EXPECTED, content)),
+        Json::String(s) if s == "NaN" => Ok(f64::NAN),
+        Json::String(s) if s == "Infinity" => Ok(f64::INFINITY),
+        Json::String(s) if s == "-Infinity" => Ok(f64::NEG_INFINITY),
+        _ => Err(invalid(EXPECTED, content)),
+    }
+}
+
+/// The bits of a float written as `0x` and exactly `digits` hex digits.
+fn from_bits(content: &Json, digits: usize) -> Option<u64> {
+    let hex = content.as_str()?.strip_prefix("0x")?;
+    match hex.len() == digits {
+        true => u64::from_str_radix(hex, 16).ok(),
+        false => None,
+    }
+}
+
+fn from_base64(b64: &str) -> Result<Vec<u8>, JsonError> {
+    base64::engine::general_purpose::STANDARD
+        .decode(b64)
+        .map_err(|err| {
+            (
+                DeserializeErrorKind::InvalidValue,
+                format!("invalid base64: {}", err),
+            )
+        })
+}
+
+/// Messagepack lengths are at most 32 bits.
+fn len(len: usize) -> Result<usize, JsonError> {
+    match u32::try_from(len) {
+        Ok(_) => Ok(len),
+        Err(_) => Err((
+            DeserializeErrorKind::LengthOverflow,
+            format!("length {} doesn't fit in messagepack", len),
+        )),
+    }
+}
+
+fn invalid(expected: &str, found: &Json) -> JsonError {
+    (
+        DeserializeErrorKind::InvalidValue,
+        format!("invalid value: {}, expected {}", found, expected),
+    )
+}
@@ -0,0 +1,37 @@
+//! Decoding bytes written before 0.0.54.
+//!
+//! Until the rmp-serde upgrade in 0.0.54 a unit enum variant was written as a single entry map
+//! from the variant name to nil, e.g. the tag of `#[serde(tag = "type")]` was written as
+//! `{"type": {"request": null}}` rather than `{"type": "request"}`.
+//! Some places, e.g. externally tagged enums, still accept the old form but others don't, such as
+//! internally tagged enums.
+
+use crate::error;
+use crate::proxy;
+use crate::DecodeLimits;
+use crate::SerializedBytesError;
+use serde::de::DeserializeOwned;
+
+/// Decode the same way as `decode` but also accept the pre-0.0.54 encoding of unit variants.
+///
+/// Only enum tags accept `{"variant": nil}`, anywhere else, e.g. a `String` field, the map is
+/// still the wrong type. The input is deserialized once and valid current bytes decode exactly
+/// the same as `decode`.
+///
+/// Tags that serde buffers before it knows what they belong to, i.e. those of internally tagged
+/// enums inside untagged or internally tagged enums, still need the current form.
+#[cfg_attr(feature = "trace", tracing::instrument)]
+pub fn decode_legacy<R, T>(input: &R) -> Result<T, SerializedBytesError>
+where
+    R: AsRef<[u8]> + ?Sized + std::fmt::Debug,
+    T: DeserializeOwned + std::fmt::Debug,
+{
+    let bytes = input.as_ref();
+    let mut deserializer = rmp_serde::Deserializer::from_read_ref(bytes);
+    let ret = error::deserialize_tracked::<T>(bytes, &mut deserializer, &proxy::State::legacy())
+        .map_err(|err| crate::deserialize_error::<T>(bytes, &DecodeLimits::UNLIMITED, || err))?;
+    crate::expect_end::<T>(bytes, &mut deserializer)?;
+    #[cfg(feature = "trace")]
+    tracing::trace!("Deserialized input into: {:?}", ret);
+    Ok(ret)
+}
@@ -1,25 +1,86 @@
+#![forbid(unsafe_code)]
+
 extern crate rmp_serde;
 extern crate serde;
 extern crate serde_json;
 
+pub use bytes::Bytes;
+pub use bytes::BytesMut;
 use serde::{Deserialize, Serialize};
 use std::convert::Infallible;
 use std::convert::TryFrom;
 
+mod canonical;
+#[cfg(feature = "async")]
+mod codec;
+mod compact;
+mod error;
+mod framing;
+#[cfg(feature = "hashing")]
+mod hashing;
+mod json;
+mod legacy;
+mod limits;
+mod msgpack;
 pub mod prelude;
+mod proxy;
+mod render;
+mod signing;
+mod stream;
+mod value;
+mod versioned;
+
+pub use canonical::decode_canonical;
+pub use canonical::encode_canonical;
+pub use canonical::validate_canonical;
+pub use canonical::CanonicalRule;
+pub use canonical::CanonicalViolation;
+#[cfg(feature = "async")]
+pub use codec::SerializedBytesCodec;
+pub use error::DeserializeError;
+pub use error::DeserializeErrorKind;
+pub use framing::FrameError;
+pub use framing::FrameStatus;
+pub use framing::Framing;
+pub use framing::LengthPrefix;
+#[cfg(feature = "hashing")]
+pub use hashing::Blake2b256;
+#[cfg(feature = "hashing")]
+pub use hashing::ContentHash;
+#[cfg(feature = "hashing")]
+pub use hashing::HashAlgorithm;
+#[cfg(feature = "hashing")]
+pub use hashing::Hashed;
+#[cfg(feature = "hashing")]
+pub use hashing::Sha256;
+pub use json::JsonBin;
+pub use json::JsonOptions;
+pub use legacy::decode_legacy;
+pub use limits::DecodeLimit;
+pub use limits::DecodeLimits;
+pub use limits::LimitExceeded;
+pub use render::BinFormat;
+pub use render::Render;
+pub use render::RenderFormat;
+pub use render::RenderOptions;
+pub use signing::Signed;
+pub use signing::Signer;
+pub use signing::Verifier;
+pub use stream::decode_from;
+pub use stream::decode_from_with_limits;
+pub use stream::encode_into;
+pub use stream::encode_into_vec;
+pub use stream::encoded_len;
+pub use value::Value;
+pub use versioned::Migrations;
+pub use versioned::SchemaVersion;
+pub use versioned::Versioned;
 
 #[cfg_attr(feature = "trace", tracing::instrument)]
 pub fn encode<T: serde::Serialize + std::fmt::Debug>(
     val: &T,
 ) -> Result<Vec<u8>, SerializedBytesError> {
-    let buf = Vec::with_capacity(128);
-    let mut se = rmp_serde::encode::Serializer::new(buf).with_struct_map();
-    val.serialize(&mut se).map_err(|err| {
-        #[cfg(feature = "trace")]
-        tracing::warn!("Failed to serialize input");
-        SerializedBytesError::Serialize(err.to_string())
-    })?;
-    let ret = se.into_inner();
+    let ret = serialize_into(val, Vec::with_capacity(128))?;
     #[cfg(feature = "trace")]
     tracing::trace!(
         "Serialized {} input into {:?}",
@@ -29,25 +90,214 @@ pub fn encode<T: serde::Serialize + std::fmt::Debug>(
     Ok(ret)
 }
 
+/// Encode the same as `encode` except that if `val` is a struct, or a struct variant, its fields
+/// are an array of their values rather than a map from field names to values.
+///
+/// The bytes are smaller and faster to decode but only make sense alongside the field order of
+/// the type, so adding, removing or reordering fields changes how every field decodes.
+/// This applies to the outermost struct only, everything inside it is encoded as by `encode`.
+///
+/// Decoding accepts structs in either form, so there is no `decode_compact`.
+#[cfg_attr(feature = "trace", tracing::instrument)]
+pub fn encode_compact<T: serde::Serialize + std::fmt::Debug>(
+    val: &T,
+) -> Result<Vec<u8>, SerializedBytesError> {
+    let mut se = rmp_serde::encode::Serializer::new(Vec::with_capacity(128)).with_struct_map();
+    val.serialize(compact::Serializer(&mut se))
+        .map_err(serialize_error)?;
+    let ret = se.into_inner();
+    #[cfg(feature = "trace")]
+    tracing::trace!(
+        "Serialized {} input compactly into {:?}",
+        std::any::type_name::<T>(),
+        ret
+    );
+    Ok(ret)
+}
+
+/// Every encode function other than `encode_compact` serializes through this so they all write
+/// identical bytes.
+pub(crate) fn serialize_into<T, W>(val: &T, writer: W) -> Result<W, SerializedBytesError>
+where
+    T: serde::Serialize,
+    W: std::io::Write,
+{
+    let mut se = rmp_serde::encode::Serializer::new(writer).with_struct_map();
+    val.serialize(&mut se).map_err(serialize_error)?;
+    Ok(se.into_inner())
+}
+
+fn serialize_error(err: rmp_serde::encode::Error) -> SerializedBytesError {
+    #[cfg(feature = "trace")]
+    tracing::warn!("Failed to serialize input");
+    SerializedBytesError::Serialize(err.to_string())
+}
+
+/// Decode exactly one messagepack value from the input.
+///
+/// Any bytes after the end of the value are an error, otherwise extra data could be smuggled
+/// along with e.g. a signed payload without changing what it decodes to.
+/// Use `decode_prefix` to read a value off the front of a larger buffer.
+///
+/// There are no resource limits, use `decode_with_limits` for untrusted input.
 #[cfg_attr(feature = "trace", tracing::instrument)]
 pub fn decode<'a, R, T>(input: &'a R) -> Result<T, SerializedBytesError>
 where
     R: AsRef<[u8]> + ?Sized + std::fmt::Debug,
     T: Deserialize<'a> + std::fmt::Debug,
 {
-    let ret = rmp_serde::from_slice(input.as_ref()).map_err(|err| {
-        #[cfg(feature = "trace")]
-        tracing::warn!(
-            "Failed to deserialize input into: {}",
-            std::any::type_name::<T>()
-        );
-        SerializedBytesError::Deserialize(err.to_string())
+    decode_exact(input.as_ref(), &DecodeLimits::UNLIMITED)
+}
+
+/// Decode exactly one messagepack value from the input, the same as `decode`, but refuse to
+/// deserialize anything that exceeds the given limits.
+#[cfg_attr(feature = "trace", tracing::instrument)]
+pub fn decode_with_limits<'a, R, T>(
+    input: &'a R,
+    limits: &DecodeLimits,
+) -> Result<T, SerializedBytesError>
+where
+    R: AsRef<[u8]> + ?Sized + std::fmt::Debug,
+    T: Deserialize<'a> + std::fmt::Debug,
+{
+    decode_exact(input.as_ref(), limits)
+}
+
+/// Decode the messagepack value at the start of the input, ignoring anything after it.
+///
+/// Returns the value along with the number of bytes it was decoded from.
+#[cfg_attr(feature = "trace", tracing::instrument)]
+pub fn decode_prefix<'a, R, T>(input: &'a R) -> Result<(T, usize), SerializedBytesError>
+where
+    R: AsRef<[u8]> + ?Sized + std::fmt::Debug,
+    T: Deserialize<'a> + std::fmt::Debug,
+{
+    decode_value(input.as_ref(), &DecodeLimits::UNLIMITED)
+}
+
+fn decode_exact<'a, T>(bytes: &'a [u8], limits: &DecodeLimits) -> Result<T, SerializedBytesError>
+where
+    T: Deserialize<'a> + std::fmt::Debug,
+{
+    if *limits != DecodeLimits::UNLIMITED {
+        let (ret, len) = decode_value(bytes, limits)?;
+        return match len == bytes.len() {
+            true => Ok(ret),
+            false => Err(trailing_bytes::<T>(bytes, len)),
+        };
+    }
+    // Without limits to check the value is deserialized without reading it first. rmp_serde
+    // doesn't tell us where a borrowing deserializer stopped, so instead we ask it for another
+    // value and expect it to find nothing.
+    let mut deserializer = rmp_serde::Deserializer::from_read_ref(bytes);
+    let ret = T::deserialize(&mut deserializer).map_err(|err| {
+        deserialize_error::<T>(bytes, &DecodeLimits::UNLIMITED, || {
+            error::explain::<T>(bytes, err)
+        })
     })?;
+    expect_end::<T>(bytes, &mut deserializer)?;
     #[cfg(feature = "trace")]
     tracing::trace!("Deserialized input into: {:?}", ret);
     Ok(ret)
 }
 
+/// Error unless `deserializer` has read all of `bytes`.
+pub(crate) fn expect_end<'a, T>(
+    bytes: &'a [u8],
+    deserializer: &mut rmp_serde::Deserializer<rmp_serde::decode::ReadRefReader<'a, [u8]>>,
+) -> Result<(), SerializedBytesError> {
+    match serde::Deserializer::deserialize_any(deserializer, End) {
+        Err(rmp_serde::decode::Error::InvalidMarkerRead(err))
+            if err.kind() == std::io::ErrorKind::UnexpectedEof =>
+        {
+            Ok(())
+        }
+        _ => {
+            let len = msgpack::value_len(bytes, 0)
+                .map_err(|err| scan_error::<T>(limits::ScanError::Read(err)))?;
+            Err(trailing_bytes::<T>(bytes, len))
+        }
+    }
+}
+
+/// Visits nothing, so deserializing it reads no more than the marker of the next value.
+struct End;
+
+impl serde::de::Visitor<'_> for End {
+    type Value = ();
+
+    fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("the end of the input")
+    }
+}
+
+fn trailing_bytes<T>(bytes: &[u8], len: usize) -> SerializedBytesError {
+    #[cfg(feature = "trace")]
+    tracing::warn!("Trailing bytes after deserialized input");
+    SerializedBytesError::Deserialize(DeserializeError {
+        kind: DeserializeErrorKind::TrailingBytes,
+        offset: Some(len),
+        type_name: std::any::type_name::<T>().to_string(),
+        path: String::new(),
+        message: format!("{} trailing bytes after value", bytes.len() - len),
+    })
+}
+
+fn decode_value<'a, T>(
+    bytes: &'a [u8],
+    limits: &DecodeLimits,
+) -> Result<(T, usize), SerializedBytesError>
+where
+    T: Deserialize<'a> + std::fmt::Debug,
+{
+    // The limits are checked before anything is deserialized, and that finds the length of the
+    // value too. Without limits only the headers are read to find the length, because rmp_serde
+    // doesn't tell us where a borrowing deserializer stopped.
+    let len = match *limits == DecodeLimits::UNLIMITED {
+        true => msgpack::value_len(bytes, 0).map_err(limits::ScanError::Read),
+        false => limits::scan(bytes, limits),
+    }
+    .map_err(|err| {
+        #[cfg(feature = "trace")]
+        tracing::warn!("Input failed checks before deserializing");
+        scan_error::<T>(err)
+    })?;
+    let bytes = &bytes[..len];
+    let ret = rmp_serde::from_slice(bytes)
+        .map_err(|err| deserialize_error::<T>(bytes, limits, || error::explain::<T>(bytes, err)))?;
+    #[cfg(feature = "trace")]
+    tracing::trace!("Deserialized input into: {:?}", ret);
+    Ok((ret, len))
+}
+
+fn scan_error<T>(err: limits::ScanError) -> SerializedBytesError {
+    match err {
+        limits::ScanError::Read(err) => SerializedBytesError::Deserialize(
+            DeserializeError::from_read(err, std::any::type_name::<T>()),
+        ),
+        limits::ScanError::Limit(exceeded) => SerializedBytesError::LimitExceeded(exceeded),
+    }
+}
+
+/// The error for failing to deserialize `bytes` into `T`.
+///
+/// Malformed messagepack is reported where it is malformed, before asking serde what went wrong.
+pub(crate) fn deserialize_error<T>(
+    bytes: &[u8],
+    limits: &DecodeLimits,
+    explain: impl FnOnce() -> DeserializeError,
+) -> SerializedBytesError {
+    #[cfg(feature = "trace")]
+    tracing::warn!(
+        "Failed to deserialize input into: {}",
+        std::any::type_name::<T>()
+    );
+    match limits::scan(bytes, limits) {
+        Err(err) => scan_error::<T>(err),
+        Ok(_) => SerializedBytesError::Deserialize(explain()),
+    }
+}
+
 #[derive(
     Clone,
     Debug,
@@ -65,13 +315,28 @@ pub enum SerializedBytesError {
     /// most likely hit a messagepack limit https://github.com/msgpack/msgpack/blob/master/spec.md#limitation
     Serialize(String),
     /// somehow failed to restore bytes
-    /// i mean, this could be anything, how do i know what's wrong with your bytes?
-    Deserialize(String),
+    /// the error has as much detail as we could find about what and where the problem is
+    Deserialize(DeserializeError),
+    /// the bytes are valid messagepack but not in canonical form
+    NonCanonical(CanonicalViolation),
+    /// decoding stopped before deserializing because the bytes exceed the given limits
+    LimitExceeded(LimitExceeded),
+    /// a length prefixed frame couldn't be written or read
+    Frame(FrameError),
+    /// signing failed or a signature doesn't match the signed bytes
+    Signature(String),
 }
 
 impl std::fmt::Display for SerializedBytesError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", self)
+        match self {
+            SerializedBytesError::Serialize(s) => write!(f, "failed to serialize: {}", s),
+            SerializedBytesError::Deserialize(e) => write!(f, "{}", e),
+            SerializedBytesError::NonCanonical(v) => write!(f, "{}", v),
+            SerializedBytesError::LimitExceeded(l) => write!(f, "{}", l),
+            SerializedBytesError::Frame(e) => write!(f, "{}", e),
+            SerializedBytesError::Signature(s) => write!(f, "signature error: {}", s),
+        }
     }
 }
 
@@ -79,11 +344,22 @@ impl From<SerializedBytesError> for String {
     fn from(sb: SerializedBytesError) -> Self {
         match sb {
             SerializedBytesError::Serialize(s) => s,
-            SerializedBytesError::Deserialize(s) => s,
+            SerializedBytesError::Deserialize(e) => e.to_string(),
+            SerializedBytesError::NonCanonical(v) => v.to_string(),
+            SerializedBytesError::LimitExceeded(l) => l.to_string(),
+            SerializedBytesError::Frame(e) => e.to_string(),
+            SerializedBytesError::Signature(s) => s,
         }
     }
 }
 
+/// For code that reads and writes with `std::io`, e.g. `SerializedBytesCodec`.
+impl From<SerializedBytesError> for std::io::Error {
+    fn from(err: SerializedBytesError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, err)
+    }
+}
+
 impl From<Infallible> for SerializedBytesError {
     fn from(_: Infallible) -> Self {
         unreachable!()
@@ -91,25 +367,37 @@ impl From<Infallible> for SerializedBytesError {
 }
 
 #[derive(Clone, Debug)]
-#[cfg_attr(
-    feature = "fuzzing",
-    derive(arbitrary::Arbitrary, proptest_derive::Arbitrary)
-)]
 /// UnsafeBytes the only way to implement a custom round trip through bytes for SerializedBytes
 /// It is intended to be an internal implementation in TryFrom implementations
 /// The assumption is that any code using UnsafeBytes is NOT valid messagepack data
 /// This allows us to enforce that all data round-tripping through SerializedBytes is via TryFrom
 /// and also allow for custom non-messagepack canonical representations of data types.
-pub struct UnsafeBytes(Vec<u8>);
+///
+/// Converting between Vec<u8>, Bytes, UnsafeBytes and SerializedBytes never copies the bytes,
+/// other than converting back to a Vec<u8> while the bytes are shared with a clone.
+pub struct UnsafeBytes(Bytes);
 
 impl From<Vec<u8>> for UnsafeBytes {
     fn from(v: Vec<u8>) -> Self {
-        Self(v)
+        Self(Bytes::from(v))
     }
 }
 
 impl From<UnsafeBytes> for Vec<u8> {
     fn from(unsafe_bytes: UnsafeBytes) -> Vec<u8> {
+        unsafe_bytes.0.into()
+    }
+}
+
+/// e.g. a `Bytes::slice` of a larger network buffer, without copying it.
+impl From<Bytes> for UnsafeBytes {
+    fn from(b: Bytes) -> Self {
+        Self(b)
+    }
+}
+
+impl From<UnsafeBytes> for Bytes {
+    fn from(unsafe_bytes: UnsafeBytes) -> Bytes {
         unsafe_bytes.0
     }
 }
@@ -148,43 +436,200 @@ impl From<SerializedBytes> for UnsafeBytes {
 /// uses #[repr(transparent)] to maximise compatibility with ffi
 /// @see https://doc.rust-lang.org/1.26.2/unstable-book/language-features/repr-transparent.html#enter-reprtransparent
 ///
-/// uses serde_bytes for efficient serialization and deserialization
+/// the bytes are reference counted `Bytes` so cloning is O(1) and clones share the same memory,
+/// e.g. fanning one entry out to many peers never copies it
+/// @see https://docs.rs/bytes
+///
+/// `Bytes` (de)serializes as a single messagepack bin rather than an array of integers
 /// without this __every byte will be individually round tripped through serde__
-/// @see https://crates.io/crates/serde_bytes
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Hash)]
-#[cfg_attr(
-    feature = "fuzzing",
-    derive(arbitrary::Arbitrary, proptest_derive::Arbitrary)
-)]
 #[repr(transparent)]
-pub struct SerializedBytes(#[serde(with = "serde_bytes")] Vec<u8>);
+pub struct SerializedBytes(Bytes);
 
 impl SerializedBytes {
-    pub fn bytes(&self) -> &Vec<u8> {
+    pub fn bytes(&self) -> &[u8] {
         &self.0
     }
+
+    /// The bytes in `range`, sharing the same buffer rather than copying them, e.g. a value
+    /// embedded in a larger one. Panics if the range is out of bounds, the same as indexing.
+    pub fn slice(&self, range: impl std::ops::RangeBounds<usize>) -> SerializedBytes {
+        Self(self.0.slice(range))
+    }
+
+    /// Render the bytes for humans, e.g. `println!("{}", sb.display_with(RenderOptions::DIAGNOSTIC))`.
+    pub fn display_with(&self, options: RenderOptions) -> Render<'_> {
+        Render::new(&self.0, options)
+    }
+
+    /// Read the bytes into a `Value` rather than a specific type.
+    pub fn to_value(&self) -> Result<Value, SerializedBytesError> {
+        value::to_value(&self.0)
+    }
+
+    /// The bytes as JSON, for clients that only speak JSON, see `JsonOptions` for how values
+    /// plain JSON can't hold are written.
+    pub fn to_json(&self, options: JsonOptions) -> Result<serde_json::Value, SerializedBytesError> {
+        json::to_json(&self.0, &options)
+    }
+
+    /// The bytes for JSON from `to_json`, or anything else following the same conventions.
+    pub fn from_json_value(json: &serde_json::Value) -> Result<Self, SerializedBytesError> {
+        Ok(Self::from(UnsafeBytes::from(json::from_json(json)?)))
+    }
+
+    /// The `Value` at a `.` separated path of map keys and array indexes, e.g.
+    /// `sb.get("entry.header.seq")`, or `None` if there is nothing at that path.
+    ///
+    /// Only the bytes on the way to the value are read, nothing else is decoded.
+    pub fn get(&self, path: &str) -> Result<Option<Value>, SerializedBytesError> {
+        value::get(&self.0, path)
+    }
+
+    /// Decode only the value at a path of map keys and array indexes, e.g.
+    /// `sb.field::<AgentKey>(&["header", "author"])`, or `None` if there is nothing at that path.
+    ///
+    /// Everything before the value on the path is skipped over using its length rather than
+    /// decoded, and nothing after it is read at all, so this is much cheaper than `decode` for
+    /// one small field of a large value.
+    pub fn field<'a, T>(&'a self, path: &[&str]) -> Result<Option<T>, SerializedBytesError>
+    where
+        T: Deserialize<'a> + std::fmt::Debug,
+    {
+        value::field(&self.0, path)
+    }
 }
 
-/// A bit of magic to convert the internal messagepack bytes into roughly equivalent JSON output
-/// for the purposes of debugging.
+#[cfg(feature = "fuzzing")]
+impl<'a> arbitrary::Arbitrary<'a> for UnsafeBytes {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Vec::<u8>::arbitrary(u).map(Self::from)
+    }
+}
+
+#[cfg(feature = "fuzzing")]
+impl<'a> arbitrary::Arbitrary<'a> for SerializedBytes {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        UnsafeBytes::arbitrary(u).map(Self::from)
+    }
+}
+
+#[cfg(feature = "fuzzing")]
+impl proptest::arbitrary::Arbitrary for UnsafeBytes {
+    type Parameters = ();
+    type Strategy =
+        proptest::strategy::Map<proptest::arbitrary::StrategyFor<Vec<u8>>, fn(Vec<u8>) -> Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        use proptest::strategy::Strategy;
+        proptest::arbitrary::any::<Vec<u8>>().prop_map(Self::from)
+    }
+}
+
+#[cfg(feature = "fuzzing")]
+impl proptest::arbitrary::Arbitrary for SerializedBytes {
+    type Parameters = ();
+    type Strategy =
+        proptest::strategy::Map<proptest::arbitrary::StrategyFor<Vec<u8>>, fn(Vec<u8>) -> Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        use proptest::strategy::Strategy;
+        proptest::arbitrary::any::<Vec<u8>>().prop_map(|v| Self::from(UnsafeBytes::from(v)))
+    }
+}
+
+/// Renders the internal messagepack bytes as roughly equivalent JSON for the purposes of
+/// debugging, see `RenderOptions::JSON`.
 /// 90% of the time you probably want this if you are a dev, to see something that "looks like" a
 /// data structure when you do {:?} in a formatted string, rather than a vector of bytes
 /// in the remaining 10% of situations where you want to debug the real messagepack bytes, call the
-/// .bytes() method on SerializedBytes and debug that.
+/// .bytes() method on SerializedBytes and debug that, or use `display_with` for other renderings.
+///
+/// The bytes are never assumed to be valid, anything that isn't exactly one messagepack value
+/// renders as `<invalid msgpack: ...>`.
 impl std::fmt::Debug for SerializedBytes {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        // NB: there is a crate::decode function which currently simply uses
-        // from_read_ref. If that ever changes, this may become inconsistent
-        // and should be changed also.
-        let mut deserializer = rmp_serde::Deserializer::from_read_ref(&self.0);
-        let writer = Vec::new();
-        let mut serializer = serde_json::ser::Serializer::new(writer);
-        if serde_transcode::transcode(&mut deserializer, &mut serializer).is_err() {
-            write!(f, "<invalid msgpack>")
-        } else {
-            let s = unsafe { String::from_utf8_unchecked(serializer.into_inner()) };
-            write!(f, "{}", s)
-        }
+        std::fmt::Display::fmt(&self.display_with(RenderOptions::JSON), f)
+    }
+}
+
+/// A borrowed view of serialized bytes, e.g. straight out of wasm linear memory or a network
+/// frame, so they can be decoded without first copying them into a `SerializedBytes`.
+///
+/// Values decoded from a `SerializedBytesRef<'a>` can borrow `&'a str` and `&'a [u8]` fields
+/// directly from the underlying bytes, see `SerializedBytesRef::decode`.
+///
+/// Much like `UnsafeBytes` nothing checks that the borrowed bytes are valid messagepack until
+/// they are decoded.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Hash)]
+#[repr(transparent)]
+pub struct SerializedBytesRef<'a>(#[serde(with = "serde_bytes", borrow)] &'a [u8]);
+
+impl<'a> SerializedBytesRef<'a> {
+    pub fn bytes(&self) -> &'a [u8] {
+        self.0
+    }
+
+    /// The same as `SerializedBytes::display_with`.
+    pub fn display_with(&self, options: RenderOptions) -> Render<'a> {
+        Render::new(self.0, options)
+    }
+
+    /// The same as `SerializedBytes::to_value`.
+    pub fn to_value(&self) -> Result<Value, SerializedBytesError> {
+        value::to_value(self.0)
+    }
+
+    /// The same as `SerializedBytes::to_json`.
+    pub fn to_json(&self, options: JsonOptions) -> Result<serde_json::Value, SerializedBytesError> {
+        json::to_json(self.0, &options)
+    }
+
+    /// The same as `SerializedBytes::get`.
+    pub fn get(&self, path: &str) -> Result<Option<Value>, SerializedBytesError> {
+        value::get(self.0, path)
+    }
+
+    /// The same as `SerializedBytes::field`, allowing the value to borrow from the bytes for `'a`.
+    pub fn field<T>(&self, path: &[&str]) -> Result<Option<T>, SerializedBytesError>
+    where
+        T: Deserialize<'a> + std::fmt::Debug,
+    {
+        value::field(self.0, path)
+    }
+
+    /// Decode the borrowed bytes, allowing the value to borrow from them for `'a`.
+    pub fn decode<T>(&self) -> Result<T, SerializedBytesError>
+    where
+        T: Deserialize<'a> + std::fmt::Debug,
+    {
+        decode(self.0)
+    }
+}
+
+impl<'a> From<&'a [u8]> for SerializedBytesRef<'a> {
+    fn from(bytes: &'a [u8]) -> Self {
+        Self(bytes)
+    }
+}
+
+impl<'a> From<&'a SerializedBytes> for SerializedBytesRef<'a> {
+    fn from(sb: &'a SerializedBytes) -> Self {
+        Self(&sb.0)
+    }
+}
+
+/// Copies the borrowed bytes, nothing more.
+impl From<SerializedBytesRef<'_>> for SerializedBytes {
+    fn from(sb: SerializedBytesRef<'_>) -> Self {
+        SerializedBytes(Bytes::copy_from_slice(sb.0))
+    }
+}
+
+/// The same JSON rendering as `SerializedBytes`.
+impl std::fmt::Debug for SerializedBytesRef<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Display::fmt(&self.display_with(RenderOptions::JSON), f)
     }
 }
 
@@ -258,32 +703,146 @@ impl std::fmt::Debug for SerializedBytes {
 /// are worth the additional up-front effort of creating a few extra shared crates/types.
 ///
 /// see the readme for more discussion around this
+///
+/// `#[derive(SerializedBytes)]` also works for generic types, every type parameter must be
+/// `Serialize + DeserializeOwned + Debug`.
+///
+/// `holochain_serial!(compact SomeType)` or `#[serialized_bytes(compact)]` on the derive encode
+/// that type with `encode_compact`, i.e. its own fields as an array rather than a map.
+///
+/// `#[serialized_bytes(with = "path::to::module")]` on the derive is for types that have their
+/// own exact byte representation rather than messagepack, e.g. Merkle trees.
+/// The module provides `to_bytes(&T) -> Result<Vec<u8>, SerializedBytesError>` and
+/// `from_bytes(&[u8]) -> Result<T, SerializedBytesError>`, and the `TryFrom` impls pass the bytes
+/// through `UnsafeBytes` so nothing else has to.
+///
+/// The `TryFrom` impls return `SerializedBytesError` unless given an error type of their own,
+/// e.g. `holochain_serial!(SomeType => SomeError)` or `#[serialized_bytes(error = "SomeError")]`
+/// on the derive, which needs `SomeError: From<SerializedBytesError>`.
+/// Anything that expects `SerializedBytesError` from the impls, e.g. `Signed` and `Versioned`,
+/// only works with types that keep the default.
 macro_rules! holochain_serial {
-    ( $( $t:ty ),* ) => {
+    // The impls for a single type, as used by `#[derive(SerializedBytes)]`:
+    // `holochain_serial!(@impl [encode fn] [error type] [impl generics] [type] [where predicates,]);`
+    ( @impl [ $encode:path ] [ $err:ty ] [ $( $generics:tt )* ] [ $t:ty ] [ $( $where:tt )* ] ) => {
+        impl<$( $generics )*> std::convert::TryFrom<&$t> for $crate::SerializedBytes
+        where
+            $( $where )*
+        {
+            type Error = $err;
+            fn try_from(t: &$t) -> std::result::Result<$crate::SerializedBytes, $err> {
+                $encode(t)
+                    .map(|v| $crate::SerializedBytes::from($crate::UnsafeBytes::from(v)))
+                    .map_err(<$err as std::convert::From<$crate::SerializedBytesError>>::from)
+            }
+        }
 
-        $(
-            impl std::convert::TryFrom<&$t> for $crate::SerializedBytes {
-                type Error = $crate::SerializedBytesError;
-                fn try_from(t: &$t) -> std::result::Result<$crate::SerializedBytes, $crate::SerializedBytesError> {
-                    $crate::encode(t).map(|v|
-                        $crate::SerializedBytes::from($crate::UnsafeBytes::from(v))
-                    )
-                }
+        impl<$( $generics )*> std::convert::TryFrom<$t> for $crate::SerializedBytes
+        where
+            $( $where )*
+        {
+            type Error = $err;
+            fn try_from(t: $t) -> std::result::Result<$crate::SerializedBytes, $err> {
+                $crate::SerializedBytes::try_from(&t)
+            }
+        }
+
+        impl<$( $generics )*> std::convert::TryFrom<$crate::SerializedBytes> for $t
+        where
+            $( $where )*
+            $t: for<'__de> $crate::prelude::Deserialize<'__de>,
+        {
+            type Error = $err;
+            fn try_from(sb: $crate::SerializedBytes) -> std::result::Result<$t, $err> {
+                $crate::decode(sb.bytes())
+                    .map_err(<$err as std::convert::From<$crate::SerializedBytesError>>::from)
+            }
+        }
+
+        // types with lifetimes can borrow from the bytes for as long as they live
+        impl<'__sb, $( $generics )*> std::convert::TryFrom<$crate::SerializedBytesRef<'__sb>> for $t
+        where
+            $( $where )*
+            $t: $crate::prelude::Deserialize<'__sb>,
+        {
+            type Error = $err;
+            fn try_from(sb: $crate::SerializedBytesRef<'__sb>) -> std::result::Result<$t, $err> {
+                $crate::decode(sb.bytes())
+                    .map_err(<$err as std::convert::From<$crate::SerializedBytesError>>::from)
             }
+        }
+    };
+
+    // `#[serialized_bytes(with = "module")]`, bytes in and out of `module::to_bytes` and
+    // `module::from_bytes` rather than messagepack:
+    // `holochain_serial!(@impl with [module] [error type] [impl generics] [type] [where predicates,]);`
+    ( @impl with [ $( $with:tt )* ] [ $err:ty ] [ $( $generics:tt )* ] [ $t:ty ] [ $( $where:tt )* ] ) => {
+        impl<$( $generics )*> std::convert::TryFrom<&$t> for $crate::SerializedBytes
+        where
+            $( $where )*
+        {
+            type Error = $err;
+            fn try_from(t: &$t) -> std::result::Result<$crate::SerializedBytes, $err> {
+                $( $with )*::to_bytes(t)
+                    .map(|v| $crate::SerializedBytes::from($crate::UnsafeBytes::from(v)))
+                    .map_err(<$err as std::convert::From<$crate::SerializedBytesError>>::from)
+            }
+        }
+
+        impl<$( $generics )*> std::convert::TryFrom<$t> for $crate::SerializedBytes
+        where
+            $( $where )*
+        {
+            type Error = $err;
+            fn try_from(t: $t) -> std::result::Result<$crate::SerializedBytes, $err> {
+                $crate::SerializedBytes::try_from(&t)
+            }
+        }
 
-            impl std::convert::TryFrom<$t> for $crate::SerializedBytes {
-                type Error = $crate::SerializedBytesError;
-                fn try_from(t: $t) -> std::result::Result<$crate::SerializedBytes, $crate::SerializedBytesError> {
-                    $crate::SerializedBytes::try_from(&t)
-                }
+        impl<$( $generics )*> std::convert::TryFrom<$crate::SerializedBytes> for $t
+        where
+            $( $where )*
+        {
+            type Error = $err;
+            fn try_from(sb: $crate::SerializedBytes) -> std::result::Result<$t, $err> {
+                $( $with )*::from_bytes(sb.bytes())
+                    .map_err(<$err as std::convert::From<$crate::SerializedBytesError>>::from)
             }
+        }
 
-            impl std::convert::TryFrom<$crate::SerializedBytes> for $t {
-                type Error = $crate::SerializedBytesError;
-                fn try_from(sb: $crate::SerializedBytes) -> std::result::Result<$t, $crate::SerializedBytesError> {
-                    $crate::decode(sb.bytes())
-                }
+        impl<'__sb, $( $generics )*> std::convert::TryFrom<$crate::SerializedBytesRef<'__sb>> for $t
+        where
+            $( $where )*
+        {
+            type Error = $err;
+            fn try_from(sb: $crate::SerializedBytesRef<'__sb>) -> std::result::Result<$t, $err> {
+                $( $with )*::from_bytes(sb.bytes())
+                    .map_err(<$err as std::convert::From<$crate::SerializedBytesError>>::from)
             }
+        }
+    };
+
+    ( @type [ $encode:path ] [ $t:ty ] ) => {
+        $crate::holochain_serial!(@impl [$encode] [$crate::SerializedBytesError] [] [$t] []);
+    };
+
+    ( @type [ $encode:path ] [ $t:ty ] => $err:ty ) => {
+        $crate::holochain_serial!(@impl [$encode] [$err] [] [$t] []);
+    };
+
+    // The fields of the type as an array rather than a map, see `encode_compact`.
+    ( compact $( $t:ty $( => $err:ty )? ),* ) => {
+
+        $(
+            $crate::holochain_serial!(@type [$crate::encode_compact] [$t] $( => $err )?);
+        )*
+
+    };
+
+    ( $( $t:ty $( => $err:ty )? ),* ) => {
+
+        $(
+            $crate::holochain_serial!(@type [$crate::encode] [$t] $( => $err )?);
         )*
 
     };
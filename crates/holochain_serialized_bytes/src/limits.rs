@@ -0,0 +1,145 @@
+//! Bounds on the resources decoding untrusted bytes can use.
+//!
+//! Bytes from the network or a wasm guest can declare huge lengths or nest arrays thousands deep
+//! in a handful of bytes. With limits, the bytes are checked against them before any
+//! deserialization (and so any allocation or recursion) happens at all. Streams can't be read
+//! ahead, so there every header is checked before the deserializer reads any further.
+
+use crate::msgpack::{walk, Event, Kind, ReadError, Token};
+
+/// The resource limits for `decode_with_limits` and `decode_from_with_limits`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DecodeLimits {
+    /// maximum nesting of arrays and maps, a top level array or map is depth 1
+    pub max_depth: usize,
+    /// maximum number of items in any one array or entries in any one map
+    pub max_container_len: usize,
+    /// maximum length in bytes of any one str, bin or ext payload
+    pub max_string_len: usize,
+    /// maximum estimated allocation for the whole value
+    /// every str, bin and ext payload byte counts, as does one pointer sized word for every array
+    /// item and every map key and value
+    pub max_alloc_bytes: usize,
+}
+
+impl DecodeLimits {
+    /// No limits at all, as used by `decode`.
+    pub const UNLIMITED: DecodeLimits = DecodeLimits {
+        max_depth: usize::MAX,
+        max_container_len: usize::MAX,
+        max_string_len: usize::MAX,
+        max_alloc_bytes: usize::MAX,
+    };
+}
+
+/// Reasonable limits for untrusted input that comfortably fit any legitimate holochain data.
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        DecodeLimits {
+            max_depth: 128,
+            max_container_len: 1 << 20,
+            max_string_len: 16 << 20,
+            max_alloc_bytes: 64 << 20,
+        }
+    }
+}
+
+/// One of the `DecodeLimits`.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+pub enum DecodeLimit {
+    Depth,
+    ContainerLen,
+    StringLen,
+    AllocBytes,
+}
+
+/// Which limit was exceeded, what it was set to and where in the bytes it was exceeded.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+pub struct LimitExceeded {
+    pub limit: DecodeLimit,
+    pub max: usize,
+    pub offset: usize,
+}
+
+impl std::fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let limit = match self.limit {
+            DecodeLimit::Depth => "max depth",
+            DecodeLimit::ContainerLen => "max container length",
+            DecodeLimit::StringLen => "max string length",
+            DecodeLimit::AllocBytes => "max allocated bytes",
+        };
+        write!(
+            f,
+            "decode limit exceeded at byte {}: {} of {}",
+            self.offset, limit, self.max
+        )
+    }
+}
+
+pub(crate) enum ScanError {
+    Read(ReadError),
+    Limit(LimitExceeded),
+}
+
+impl From<ReadError> for ScanError {
+    fn from(err: ReadError) -> Self {
+        ScanError::Read(err)
+    }
+}
+
+/// Walk the value at the start of `bytes`, checking it against `limits`,
+/// and return the offset it ends at.
+pub(crate) fn scan(bytes: &[u8], limits: &DecodeLimits) -> Result<usize, ScanError> {
+    let mut alloc_bytes: usize = 0;
+    walk(bytes, 0, |event| match event {
+        Event::Token { token, depth } => {
+            check(token, depth, limits, &mut alloc_bytes).map_err(ScanError::Limit)
+        }
+        Event::Key { .. } => Ok(()),
+    })
+}
+
+/// Check a single token that is `depth` arrays and maps deep against `limits`, adding its
+/// estimated allocation to `alloc_bytes`.
+pub(crate) fn check(
+    token: &Token,
+    depth: usize,
+    limits: &DecodeLimits,
+    alloc_bytes: &mut usize,
+) -> Result<(), LimitExceeded> {
+    let exceeded = |limit, max| {
+        Err(LimitExceeded {
+            limit,
+            max,
+            offset: token.offset,
+        })
+    };
+    let alloc = match token.kind {
+        Kind::Array(len) | Kind::Map(len) => {
+            if depth >= limits.max_depth {
+                return exceeded(DecodeLimit::Depth, limits.max_depth);
+            }
+            if len > limits.max_container_len {
+                return exceeded(DecodeLimit::ContainerLen, limits.max_container_len);
+            }
+            token.items().saturating_mul(std::mem::size_of::<usize>())
+        }
+        Kind::Str(len) | Kind::Bin(len) | Kind::Ext(_, len) => {
+            if len > limits.max_string_len {
+                return exceeded(DecodeLimit::StringLen, limits.max_string_len);
+            }
+            len
+        }
+        _ => 0,
+    };
+    *alloc_bytes = alloc_bytes.saturating_add(alloc);
+    if *alloc_bytes > limits.max_alloc_bytes {
+        return exceeded(DecodeLimit::AllocBytes, limits.max_alloc_bytes);
+    }
+    Ok(())
+}
@@ -0,0 +1,330 @@
+//! Low level MessagePack reading and writing over byte slices.
+//!
+//! rmp_serde does the heavy lifting of moving rust types in and out of messagepack, but it
+//! doesn't tell us anything about the bytes themselves as it goes.
+//! Everything that needs to reason about the raw bytes (canonical form, offsets, etc.) walks
+//! them one token at a time with this module instead.
+//! @see https://github.com/msgpack/msgpack/blob/master/spec.md
+
+use rmp::Marker;
+
+/// What a single token represents, with any length it declares.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Kind {
+    Nil,
+    Bool(bool),
+    /// Any positive fixint or uint marker.
+    UInt(u64),
+    /// Any negative fixint or int marker.
+    Int(i64),
+    F32(f32),
+    F64(f64),
+    /// Number of payload bytes.
+    Str(usize),
+    /// Number of payload bytes.
+    Bin(usize),
+    /// Ext type and number of payload bytes.
+    Ext(i8, usize),
+    /// Number of items.
+    Array(usize),
+    /// Number of key/value pairs.
+    Map(usize),
+}
+
+/// A single marker with its header (lengths, scalar data) fully read.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Token {
+    pub(crate) kind: Kind,
+    /// Offset of the marker byte.
+    pub(crate) offset: usize,
+    /// Length of the marker plus everything after it other than payload bytes and child items.
+    pub(crate) header_len: usize,
+}
+
+impl Token {
+    /// Offset of the first byte after the header.
+    pub(crate) fn body_start(&self) -> usize {
+        self.offset + self.header_len
+    }
+
+    /// Number of raw bytes following the header, i.e. str, bin and ext payloads.
+    pub(crate) fn payload_len(&self) -> usize {
+        match self.kind {
+            Kind::Str(len) | Kind::Bin(len) | Kind::Ext(_, len) => len,
+            _ => 0,
+        }
+    }
+
+    /// Offset of the first byte after the header and payload.
+    /// For arrays and maps this is where the first child item starts.
+    pub(crate) fn end(&self) -> usize {
+        self.body_start() + self.payload_len()
+    }
+
+    /// The raw payload bytes of a str, bin or ext.
+    pub(crate) fn payload<'a>(&self, bytes: &'a [u8]) -> &'a [u8] {
+        &bytes[self.body_start()..self.end()]
+    }
+
+    /// Number of child items that follow, counting map keys and values separately.
+    pub(crate) fn items(&self) -> usize {
+        match self.kind {
+            Kind::Array(len) => len,
+            Kind::Map(len) => len.saturating_mul(2),
+            _ => 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ReadError {
+    /// The input ends part way through the value starting at `offset`.
+    UnexpectedEof { offset: usize },
+    /// The reserved marker 0xc1 was found at `offset`.
+    ReservedMarker { offset: usize },
+    /// The array or map at `offset` declares more items than could possibly fit in the input.
+    LengthOverflow { offset: usize, len: usize },
+}
+
+impl std::fmt::Display for ReadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReadError::UnexpectedEof { offset } => {
+                write!(f, "unexpected end of input in value at byte {}", offset)
+            }
+            ReadError::ReservedMarker { offset } => {
+                write!(f, "reserved marker 0xc1 at byte {}", offset)
+            }
+            ReadError::LengthOverflow { offset, len } => write!(
+                f,
+                "declared length {} at byte {} exceeds the remaining input",
+                len, offset
+            ),
+        }
+    }
+}
+
+struct Cursor<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    pos: usize,
+}
+
+impl Cursor<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], ReadError> {
+        let end = self.pos + N;
+        let taken = self
+            .bytes
+            .get(self.pos..end)
+            .and_then(|b| <[u8; N]>::try_from(b).ok())
+            .ok_or(ReadError::UnexpectedEof {
+                offset: self.offset,
+            })?;
+        self.pos = end;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, ReadError> {
+        self.take::<1>().map(|[b]| b)
+    }
+
+    fn u16(&mut self) -> Result<u16, ReadError> {
+        self.take().map(u16::from_be_bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, ReadError> {
+        self.take().map(u32::from_be_bytes)
+    }
+
+    fn ext_type(&mut self) -> Result<i8, ReadError> {
+        self.take().map(i8::from_be_bytes)
+    }
+}
+
+/// Reads the token starting at `offset`.
+///
+/// Payloads are bounds checked but not read, child items of arrays and maps are not read at all.
+pub(crate) fn read_token(bytes: &[u8], offset: usize) -> Result<Token, ReadError> {
+    let token = read_head(bytes, offset)?;
+    let remaining = bytes.len() - token.body_start();
+    if token.payload_len() > remaining {
+        return Err(ReadError::UnexpectedEof { offset });
+    }
+    // Every item takes at least one byte, so this is a cheap upper bound on sane lengths.
+    if token.items() > remaining {
+        return Err(ReadError::LengthOverflow {
+            offset,
+            len: token.items(),
+        });
+    }
+    Ok(token)
+}
+
+/// Reads only the marker and header of the token starting at `offset`, so `bytes` can end right
+/// after the header.
+pub(crate) fn read_head(bytes: &[u8], offset: usize) -> Result<Token, ReadError> {
+    let first = *bytes
+        .get(offset)
+        .ok_or(ReadError::UnexpectedEof { offset })?;
+    let marker = Marker::from_u8(first);
+    let mut c = Cursor {
+        bytes,
+        offset,
+        pos: offset + 1,
+    };
+    let kind = match marker {
+        Marker::FixPos(n) => Kind::UInt(n.into()),
+        Marker::FixNeg(n) => Kind::Int(n.into()),
+        Marker::Null => Kind::Nil,
+        Marker::True => Kind::Bool(true),
+        Marker::False => Kind::Bool(false),
+        Marker::U8 => Kind::UInt(c.u8()?.into()),
+        Marker::U16 => Kind::UInt(c.u16()?.into()),
+        Marker::U32 => Kind::UInt(c.u32()?.into()),
+        Marker::U64 => Kind::UInt(u64::from_be_bytes(c.take()?)),
+        Marker::I8 => Kind::Int(i8::from_be_bytes(c.take()?).into()),
+        Marker::I16 => Kind::Int(i16::from_be_bytes(c.take()?).into()),
+        Marker::I32 => Kind::Int(i32::from_be_bytes(c.take()?).into()),
+        Marker::I64 => Kind::Int(i64::from_be_bytes(c.take()?)),
+        Marker::F32 => Kind::F32(f32::from_be_bytes(c.take()?)),
+        Marker::F64 => Kind::F64(f64::from_be_bytes(c.take()?)),
+        Marker::FixStr(len) => Kind::Str(len.into()),
+        Marker::Str8 => Kind::Str(c.u8()?.into()),
+        Marker::Str16 => Kind::Str(c.u16()?.into()),
+        Marker::Str32 => Kind::Str(c.u32()? as usize),
+        Marker::Bin8 => Kind::Bin(c.u8()?.into()),
+        Marker::Bin16 => Kind::Bin(c.u16()?.into()),
+        Marker::Bin32 => Kind::Bin(c.u32()? as usize),
+        Marker::FixArray(len) => Kind::Array(len.into()),
+        Marker::Array16 => Kind::Array(c.u16()?.into()),
+        Marker::Array32 => Kind::Array(c.u32()? as usize),
+        Marker::FixMap(len) => Kind::Map(len.into()),
+        Marker::Map16 => Kind::Map(c.u16()?.into()),
+        Marker::Map32 => Kind::Map(c.u32()? as usize),
+        Marker::FixExt1 => Kind::Ext(c.ext_type()?, 1),
+        Marker::FixExt2 => Kind::Ext(c.ext_type()?, 2),
+        Marker::FixExt4 => Kind::Ext(c.ext_type()?, 4),
+        Marker::FixExt8 => Kind::Ext(c.ext_type()?, 8),
+        Marker::FixExt16 => Kind::Ext(c.ext_type()?, 16),
+        Marker::Ext8 => {
+            let len = c.u8()?.into();
+            Kind::Ext(c.ext_type()?, len)
+        }
+        Marker::Ext16 => {
+            let len = c.u16()?.into();
+            Kind::Ext(c.ext_type()?, len)
+        }
+        Marker::Ext32 => {
+            let len = c.u32()? as usize;
+            Kind::Ext(c.ext_type()?, len)
+        }
+        Marker::Reserved => return Err(ReadError::ReservedMarker { offset }),
+    };
+    Ok(Token {
+        kind,
+        offset,
+        header_len: c.pos - offset,
+    })
+}
+
+/// Something a walk came across.
+pub(crate) enum Event<'t> {
+    /// A token, before any of its child items, and how many arrays and maps enclose it.
+    Token { token: &'t Token, depth: usize },
+    /// A complete map key, as the byte range of its encoding, along with the previous key in the
+    /// same map if there is one.
+    Key {
+        key: (usize, usize),
+        prev: Option<(usize, usize)>,
+    },
+}
+
+struct Frame {
+    /// Child items left to read, counting map keys and values separately.
+    remaining: usize,
+    is_map: bool,
+    /// Offset the current child item starts at.
+    item_start: usize,
+    prev_key: Option<(usize, usize)>,
+}
+
+/// Walks the complete value starting at `offset`, passing everything it comes across to `visit`,
+/// and returns the offset the value ends at.
+///
+/// This doesn't recurse so it is safe to use on arbitrarily deeply nested untrusted input.
+pub(crate) fn walk<E: From<ReadError>>(
+    bytes: &[u8],
+    offset: usize,
+    mut visit: impl FnMut(Event<'_>) -> Result<(), E>,
+) -> Result<usize, E> {
+    let mut stack: Vec<Frame> = Vec::new();
+    let mut pos = offset;
+    loop {
+        let token = read_token(bytes, pos)?;
+        visit(Event::Token {
+            token: &token,
+            depth: stack.len(),
+        })?;
+        pos = token.end();
+        if token.items() > 0 {
+            stack.push(Frame {
+                remaining: token.items(),
+                is_map: matches!(token.kind, Kind::Map(_)),
+                item_start: pos,
+                prev_key: None,
+            });
+            continue;
+        }
+
+        // The token is a complete item so finish off every container it completes.
+        while let Some(frame) = stack.last_mut() {
+            if frame.is_map && frame.remaining % 2 == 0 {
+                let key = (frame.item_start, pos);
+                visit(Event::Key {
+                    key,
+                    prev: frame.prev_key,
+                })?;
+                frame.prev_key = Some(key);
+            }
+            frame.remaining -= 1;
+            if frame.remaining > 0 {
+                frame.item_start = pos;
+                break;
+            }
+            stack.pop();
+        }
+        if stack.is_empty() {
+            return Ok(pos);
+        }
+    }
+}
+
+/// Offset the complete value starting at `offset` ends at.
+pub(crate) fn skip(bytes: &[u8], offset: usize) -> Result<usize, ReadError> {
+    walk(bytes, offset, |_| Ok::<_, ReadError>(()))
+}
+
+/// Length in bytes of the complete value starting at `offset`.
+pub(crate) fn value_len(bytes: &[u8], offset: usize) -> Result<usize, ReadError> {
+    skip(bytes, offset).map(|end| end - offset)
+}
+
+/// Writes the smallest possible header for `kind`, exactly as rmp_serde would.
+/// Payloads and child items are the caller's responsibility.
+pub(crate) fn write_head(out: &mut Vec<u8>, kind: Kind) {
+    let written = match kind {
+        Kind::Nil => rmp::encode::write_nil(out).is_ok(),
+        Kind::Bool(b) => rmp::encode::write_bool(out, b).is_ok(),
+        Kind::UInt(n) => rmp::encode::write_uint(out, n).is_ok(),
+        Kind::Int(n) => rmp::encode::write_sint(out, n).is_ok(),
+        Kind::F32(n) => rmp::encode::write_f32(out, n).is_ok(),
+        Kind::F64(n) => rmp::encode::write_f64(out, n).is_ok(),
+        Kind::Str(len) => rmp::encode::write_str_len(out, len as u32).is_ok(),
+        Kind::Bin(len) => rmp::encode::write_bin_len(out, len as u32).is_ok(),
+        Kind::Ext(ty, len) => rmp::encode::write_ext_meta(out, len as u32, ty).is_ok(),
+        Kind::Array(len) => rmp::encode::write_array_len(out, len as u32).is_ok(),
+        Kind::Map(len) => rmp::encode::write_map_len(out, len as u32).is_ok(),
+    };
+    // Writing into a Vec can't fail.
+    debug_assert!(written);
+}
@@ -7,6 +7,7 @@ pub use std::convert::TryInto;
 
 /// this is everything downstream consumers need from this crate
 pub use crate::holochain_serial;
+pub use crate::DecodeLimits;
 pub use crate::SerializedBytes;
 pub use crate::SerializedBytesError;
 pub use crate::UnsafeBytes;
@@ -14,4 +15,15 @@ pub use crate::UnsafeBytes;
 pub use holochain_serialized_bytes_derive::SerializedBytes;
 
 pub use crate::decode;
+pub use crate::decode_canonical;
+pub use crate::decode_from;
+pub use crate::decode_from_with_limits;
+pub use crate::decode_legacy;
+pub use crate::decode_prefix;
+pub use crate::decode_with_limits;
 pub use crate::encode;
+pub use crate::encode_canonical;
+pub use crate::encode_compact;
+pub use crate::encode_into;
+pub use crate::encode_into_vec;
+pub use crate::encoded_len;
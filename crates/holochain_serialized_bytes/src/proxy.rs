@@ -0,0 +1,504 @@
+//! A deserializer that wraps another one for the slow paths that need more than rmp_serde
+//! reports.
+//!
+//! rmp_serde flattens every error a `Deserialize` implementation raises (missing field, unknown
+//! variant, etc.) into a message. The wrapper hands implementations an error type that keeps the
+//! kind, and passes it through the wrapped deserializer, which only takes messages, on the side.
+//!
+//! For `decode_legacy` it also accepts enum tags in their pre-0.0.54 form.
+
+use crate::DeserializeErrorKind;
+use serde::de::{self, DeserializeSeed};
+use std::cell::RefCell;
+use std::fmt;
+
+/// Shared by everything wrapped for one deserialization.
+#[derive(Default)]
+pub(crate) struct State {
+    /// Whether enum tags can be `{"variant": nil}`, as they were before 0.0.54.
+    legacy: bool,
+    /// The kind and message of the last error raised by a `Deserialize` implementation while it
+    /// passes through the wrapped deserializer as a message.
+    raised: RefCell<Option<(DeserializeErrorKind, String)>>,
+}
+
+impl State {
+    pub(crate) fn legacy() -> Self {
+        Self {
+            legacy: true,
+            ..Self::default()
+        }
+    }
+
+    /// Hand `err` to the wrapped deserializer.
+    fn raise<E: de::Error>(&self, err: Error<E>) -> E {
+        match err {
+            Error::Inner(err) => err,
+            Error::Raised(kind, message) => {
+                let err = E::custom(&message);
+                *self.raised.borrow_mut() = Some((kind, message));
+                err
+            }
+        }
+    }
+
+    /// Take back an error the wrapped deserializer returned.
+    fn recover<E: de::Error>(&self, err: E) -> Error<E> {
+        let mut raised = self.raised.borrow_mut();
+        match raised.take() {
+            Some((kind, message)) if err.to_string() == message => Error::Raised(kind, message),
+            other => {
+                *raised = other;
+                Error::Inner(err)
+            }
+        }
+    }
+}
+
+/// Either an error from the wrapped deserializer or one raised by a `Deserialize`
+/// implementation.
+#[derive(Debug)]
+pub(crate) enum Error<E> {
+    Inner(E),
+    Raised(DeserializeErrorKind, String),
+}
+
+impl<E: fmt::Display> fmt::Display for Error<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Inner(err) => err.fmt(f),
+            Error::Raised(_, message) => f.write_str(message),
+        }
+    }
+}
+
+impl<E: std::error::Error> std::error::Error for Error<E> {}
+
+/// Messages are the same as serde's defaults.
+impl<E: de::Error> de::Error for Error<E> {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error::Raised(DeserializeErrorKind::Custom, msg.to_string())
+    }
+
+    fn invalid_type(unexp: de::Unexpected, exp: &dyn de::Expected) -> Self {
+        raised(
+            DeserializeErrorKind::TypeMismatch,
+            de::Error::invalid_type(unexp, exp),
+        )
+    }
+
+    fn invalid_value(unexp: de::Unexpected, exp: &dyn de::Expected) -> Self {
+        raised(
+            DeserializeErrorKind::InvalidValue,
+            de::Error::invalid_value(unexp, exp),
+        )
+    }
+
+    fn invalid_length(len: usize, exp: &dyn de::Expected) -> Self {
+        raised(
+            DeserializeErrorKind::LengthMismatch,
+            de::Error::invalid_length(len, exp),
+        )
+    }
+
+    fn unknown_variant(variant: &str, expected: &'static [&'static str]) -> Self {
+        raised(
+            DeserializeErrorKind::UnknownVariant,
+            de::Error::unknown_variant(variant, expected),
+        )
+    }
+
+    fn unknown_field(field: &str, expected: &'static [&'static str]) -> Self {
+        raised(
+            DeserializeErrorKind::UnknownField,
+            de::Error::unknown_field(field, expected),
+        )
+    }
+
+    fn missing_field(field: &'static str) -> Self {
+        raised(
+            DeserializeErrorKind::MissingField,
+            de::Error::missing_field(field),
+        )
+    }
+
+    fn duplicate_field(field: &'static str) -> Self {
+        raised(
+            DeserializeErrorKind::DuplicateField,
+            de::Error::duplicate_field(field),
+        )
+    }
+}
+
+fn raised<E>(kind: DeserializeErrorKind, err: de::value::Error) -> Error<E> {
+    Error::Raised(kind, err.to_string())
+}
+
+/// Wraps `de` and everything deserialized from it.
+pub(crate) struct Deserializer<'s, D> {
+    de: D,
+    state: &'s State,
+    /// Whether this is a map key, where identifiers are field names and never enum tags.
+    key: bool,
+}
+
+impl<'s, D> Deserializer<'s, D> {
+    pub(crate) fn new(de: D, state: &'s State) -> Self {
+        Self {
+            de,
+            state,
+            key: false,
+        }
+    }
+}
+
+macro_rules! forward_deserialize {
+    ($($method:ident($($arg:ident: $ty:ty),*);)*) => {$(
+        fn $method<V: de::Visitor<'de>>(
+            self,
+            $($arg: $ty,)*
+            visitor: V,
+        ) -> Result<V::Value, Self::Error> {
+            let state = self.state;
+            self.de
+                .$method($($arg,)* Visitor::new(visitor, state))
+                .map_err(|err| state.recover(err))
+        }
+    )*};
+}
+
+impl<'de, D: de::Deserializer<'de>> de::Deserializer<'de> for Deserializer<'_, D> {
+    type Error = Error<D::Error>;
+
+    forward_deserialize! {
+        deserialize_any();
+        deserialize_bool();
+        deserialize_i8();
+        deserialize_i16();
+        deserialize_i32();
+        deserialize_i64();
+        deserialize_i128();
+        deserialize_u8();
+        deserialize_u16();
+        deserialize_u32();
+        deserialize_u64();
+        deserialize_u128();
+        deserialize_f32();
+        deserialize_f64();
+        deserialize_char();
+        deserialize_str();
+        deserialize_string();
+        deserialize_bytes();
+        deserialize_byte_buf();
+        deserialize_option();
+        deserialize_unit();
+        deserialize_unit_struct(name: &'static str);
+        deserialize_newtype_struct(name: &'static str);
+        deserialize_seq();
+        deserialize_tuple(len: usize);
+        deserialize_tuple_struct(name: &'static str, len: usize);
+        deserialize_map();
+        deserialize_struct(name: &'static str, fields: &'static [&'static str]);
+        deserialize_enum(name: &'static str, variants: &'static [&'static str]);
+        deserialize_ignored_any();
+    }
+
+    /// rmp_serde already takes legacy tags where it deserializes an enum itself, i.e. the
+    /// externally and adjacently tagged ones. Internally tagged enums read their tag as an
+    /// identifier from a map value, so legacy tags are taken there.
+    fn deserialize_identifier<V: de::Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        let state = self.state;
+        let visitor = Visitor {
+            legacy_tag: state.legacy && !self.key,
+            ..Visitor::new(visitor, state)
+        };
+        self.de
+            .deserialize_identifier(visitor)
+            .map_err(|err| state.recover(err))
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.de.is_human_readable()
+    }
+}
+
+struct Visitor<'s, V> {
+    inner: V,
+    state: &'s State,
+    /// Whether `inner` also takes its identifier as the key of `{"variant": nil}`.
+    legacy_tag: bool,
+}
+
+impl<'s, V> Visitor<'s, V> {
+    fn new(inner: V, state: &'s State) -> Self {
+        Self {
+            inner,
+            state,
+            legacy_tag: false,
+        }
+    }
+}
+
+macro_rules! forward_visit {
+    ($($method:ident($ty:ty);)*) => {$(
+        fn $method<E: de::Error>(self, v: $ty) -> Result<V::Value, E> {
+            let state = self.state;
+            self.inner.$method(v).map_err(|err| state.raise(err))
+        }
+    )*};
+}
+
+impl<'de, V: de::Visitor<'de>> de::Visitor<'de> for Visitor<'_, V> {
+    type Value = V::Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.expecting(f)
+    }
+
+    forward_visit! {
+        visit_bool(bool);
+        visit_i8(i8);
+        visit_i16(i16);
+        visit_i32(i32);
+        visit_i64(i64);
+        visit_i128(i128);
+        visit_u8(u8);
+        visit_u16(u16);
+        visit_u32(u32);
+        visit_u64(u64);
+        visit_u128(u128);
+        visit_f32(f32);
+        visit_f64(f64);
+        visit_char(char);
+        visit_str(&str);
+        visit_borrowed_str(&'de str);
+        visit_string(String);
+        visit_bytes(&[u8]);
+        visit_borrowed_bytes(&'de [u8]);
+        visit_byte_buf(Vec<u8>);
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<V::Value, E> {
+        let state = self.state;
+        self.inner.visit_none().map_err(|err| state.raise(err))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<V::Value, E> {
+        let state = self.state;
+        self.inner.visit_unit().map_err(|err| state.raise(err))
+    }
+
+    fn visit_some<D: de::Deserializer<'de>>(self, de: D) -> Result<V::Value, D::Error> {
+        let state = self.state;
+        self.inner
+            .visit_some(Deserializer::new(de, state))
+            .map_err(|err| state.raise(err))
+    }
+
+    fn visit_newtype_struct<D: de::Deserializer<'de>>(self, de: D) -> Result<V::Value, D::Error> {
+        let state = self.state;
+        self.inner
+            .visit_newtype_struct(Deserializer::new(de, state))
+            .map_err(|err| state.raise(err))
+    }
+
+    fn visit_seq<A: de::SeqAccess<'de>>(self, access: A) -> Result<V::Value, A::Error> {
+        let state = self.state;
+        self.inner
+            .visit_seq(Access {
+                inner: access,
+                state,
+            })
+            .map_err(|err| state.raise(err))
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, access: A) -> Result<V::Value, A::Error> {
+        let state = self.state;
+        let mut access = Access {
+            inner: access,
+            state,
+        };
+        if !self.legacy_tag || de::MapAccess::size_hint(&access) != Some(1) {
+            return self.inner.visit_map(access).map_err(|err| state.raise(err));
+        }
+        de::MapAccess::next_key_seed(&mut access, Tag(self.inner))
+            .and_then(|tag| match tag {
+                Some(tag) => {
+                    de::MapAccess::next_value::<()>(&mut access)?;
+                    Ok(tag)
+                }
+                None => Err(de::Error::invalid_length(0, &"a map of the tag to nil")),
+            })
+            .map_err(|err| state.raise(err))
+    }
+
+    fn visit_enum<A: de::EnumAccess<'de>>(self, access: A) -> Result<V::Value, A::Error> {
+        let state = self.state;
+        self.inner
+            .visit_enum(Access {
+                inner: access,
+                state,
+            })
+            .map_err(|err| state.raise(err))
+    }
+}
+
+/// Reads the key of a legacy enum tag, which is the tag itself.
+struct Tag<V>(V);
+
+impl<'de, V: de::Visitor<'de>> DeserializeSeed<'de> for Tag<V> {
+    type Value = V::Value;
+
+    fn deserialize<D: de::Deserializer<'de>>(self, de: D) -> Result<V::Value, D::Error> {
+        de.deserialize_identifier(self.0)
+    }
+}
+
+struct Seed<'s, S> {
+    inner: S,
+    state: &'s State,
+    key: bool,
+}
+
+impl<'de, S: DeserializeSeed<'de>> DeserializeSeed<'de> for Seed<'_, S> {
+    type Value = S::Value;
+
+    fn deserialize<D: de::Deserializer<'de>>(self, de: D) -> Result<S::Value, D::Error> {
+        let state = self.state;
+        let key = self.key;
+        self.inner
+            .deserialize(Deserializer { de, state, key })
+            .map_err(|err| state.raise(err))
+    }
+}
+
+/// Wraps the seq, map, enum and variant accesses a deserializer gives its visitors.
+struct Access<'s, A> {
+    inner: A,
+    state: &'s State,
+}
+
+impl<'s, A> Access<'s, A> {
+    fn seed<S>(&self, inner: S) -> Seed<'s, S> {
+        Seed {
+            inner,
+            state: self.state,
+            key: false,
+        }
+    }
+
+    fn key_seed<S>(&self, inner: S) -> Seed<'s, S> {
+        Seed {
+            key: true,
+            ..self.seed(inner)
+        }
+    }
+}
+
+impl<'de, A: de::SeqAccess<'de>> de::SeqAccess<'de> for Access<'_, A> {
+    type Error = Error<A::Error>;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Self::Error> {
+        let seed = self.seed(seed);
+        self.inner
+            .next_element_seed(seed)
+            .map_err(|err| self.state.recover(err))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.inner.size_hint()
+    }
+}
+
+impl<'de, A: de::MapAccess<'de>> de::MapAccess<'de> for Access<'_, A> {
+    type Error = Error<A::Error>;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        let seed = self.key_seed(seed);
+        self.inner
+            .next_key_seed(seed)
+            .map_err(|err| self.state.recover(err))
+    }
+
+    fn next_value_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<T::Value, Self::Error> {
+        let seed = self.seed(seed);
+        self.inner
+            .next_value_seed(seed)
+            .map_err(|err| self.state.recover(err))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.inner.size_hint()
+    }
+}
+
+impl<'de, 's, A: de::EnumAccess<'de>> de::EnumAccess<'de> for Access<'s, A> {
+    type Error = Error<A::Error>;
+    type Variant = Access<'s, A::Variant>;
+
+    fn variant_seed<T: DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<(T::Value, Self::Variant), Self::Error> {
+        let state = self.state;
+        let seed = self.key_seed(seed);
+        match self.inner.variant_seed(seed) {
+            Ok((value, inner)) => Ok((value, Access { inner, state })),
+            Err(err) => Err(state.recover(err)),
+        }
+    }
+}
+
+impl<'de, A: de::VariantAccess<'de>> de::VariantAccess<'de> for Access<'_, A> {
+    type Error = Error<A::Error>;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        let state = self.state;
+        self.inner.unit_variant().map_err(|err| state.recover(err))
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<T::Value, Self::Error> {
+        let state = self.state;
+        let seed = self.seed(seed);
+        self.inner
+            .newtype_variant_seed(seed)
+            .map_err(|err| state.recover(err))
+    }
+
+    fn tuple_variant<V: de::Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        let state = self.state;
+        self.inner
+            .tuple_variant(len, Visitor::new(visitor, state))
+            .map_err(|err| state.recover(err))
+    }
+
+    fn struct_variant<V: de::Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        let state = self.state;
+        self.inner
+            .struct_variant(fields, Visitor::new(visitor, state))
+            .map_err(|err| state.recover(err))
+    }
+}
@@ -0,0 +1,409 @@
+//! Human readable renderings of serialized bytes, for debugging and logging.
+//!
+//! Everything is rendered straight from the messagepack tokens rather than by deserializing into
+//! some other data model first, so anything messagepack can hold can be rendered: bin payloads,
+//! ext types, maps with non-string keys and the exact integer and float markers.
+
+use crate::msgpack::{read_token, value_len, walk, Event, Kind, ReadError, Token};
+use base64::Engine;
+use std::collections::HashSet;
+use std::fmt::Write;
+
+/// The overall syntax of a rendering.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RenderFormat {
+    /// JSON, as close as messagepack allows.
+    /// Maps with keys that aren't strings are `{"$map":[[key,value],...]}` as in
+    /// `SerializedBytes::to_json`, NaN and infinite floats are `null` and ext values are
+    /// `{"$ext":[type,data]}`.
+    Json,
+    /// Diagnostic notation in the style of CBOR, which keeps the type of everything.
+    /// Every integer written with more than a single byte marker is tagged with the marker, e.g.
+    /// `5_u64` or `-1_i8`, floats are tagged `_f32` or `_f64` and ext values are
+    /// `ext(type, data)`.
+    Diagnostic,
+}
+
+/// How bin and ext payloads are written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BinFormat {
+    /// An array of byte values, e.g. `[1,2,3]`, or `bin[1, 2, 3]` in diagnostic notation.
+    Array,
+    /// Lower case hex, e.g. `"010203"`, or `h'010203'` in diagnostic notation.
+    Hex,
+    /// Standard padded base64, e.g. `"AQID"`, or `b64'AQID'` in diagnostic notation.
+    Base64,
+}
+
+/// How `SerializedBytes::display_with` renders the bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RenderOptions {
+    pub format: RenderFormat,
+    /// one item per line, indented by nesting
+    pub pretty: bool,
+    pub bin: BinFormat,
+    /// maximum number of bytes of any one bin or ext payload to write, the rest are replaced by
+    /// a count of how many bytes were left out, e.g. `"…(+10 bytes)"`
+    pub max_bin_len: usize,
+}
+
+impl RenderOptions {
+    /// Compact JSON with bins as arrays of bytes, the same as `{:?}`.
+    pub const JSON: RenderOptions = RenderOptions {
+        format: RenderFormat::Json,
+        pretty: false,
+        bin: BinFormat::Array,
+        max_bin_len: usize::MAX,
+    };
+
+    /// Indented JSON with bins as hex.
+    pub const PRETTY_JSON: RenderOptions = RenderOptions {
+        format: RenderFormat::Json,
+        pretty: true,
+        bin: BinFormat::Hex,
+        max_bin_len: usize::MAX,
+    };
+
+    /// Diagnostic notation with bins as hex.
+    pub const DIAGNOSTIC: RenderOptions = RenderOptions {
+        format: RenderFormat::Diagnostic,
+        pretty: false,
+        bin: BinFormat::Hex,
+        max_bin_len: usize::MAX,
+    };
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self::JSON
+    }
+}
+
+/// Serialized bytes along with how to render them, see `SerializedBytes::display_with`.
+#[derive(Clone, Copy, Debug)]
+pub struct Render<'a> {
+    bytes: &'a [u8],
+    options: RenderOptions,
+}
+
+impl<'a> Render<'a> {
+    pub(crate) fn new(bytes: &'a [u8], options: RenderOptions) -> Self {
+        Self { bytes, options }
+    }
+}
+
+/// Bytes that aren't exactly one messagepack value render as `<invalid msgpack: ...>`.
+impl std::fmt::Display for Render<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut pair_maps = HashSet::new();
+        let len = match self.options.format {
+            RenderFormat::Json => find_pair_maps(self.bytes, &mut pair_maps),
+            RenderFormat::Diagnostic => value_len(self.bytes, 0),
+        };
+        match len {
+            Ok(len) if len == self.bytes.len() => {}
+            Ok(len) => {
+                return write!(
+                    f,
+                    "<invalid msgpack: {} trailing bytes after value>",
+                    self.bytes.len() - len
+                )
+            }
+            Err(err) => return write!(f, "<invalid msgpack: {}>", err),
+        }
+        Renderer {
+            bytes: self.bytes,
+            options: &self.options,
+            pair_maps,
+            f,
+        }
+        .render()
+        .map_err(|_| std::fmt::Error)
+    }
+}
+
+enum RenderError {
+    Read,
+    Fmt,
+}
+
+impl From<ReadError> for RenderError {
+    fn from(_: ReadError) -> Self {
+        RenderError::Read
+    }
+}
+
+impl From<std::fmt::Error> for RenderError {
+    fn from(_: std::fmt::Error) -> Self {
+        RenderError::Fmt
+    }
+}
+
+struct Frame {
+    /// Number of child items, counting map keys and values separately.
+    items: usize,
+    /// Child items written so far.
+    written: usize,
+    is_map: bool,
+    /// A map written as an array of `[key,value]` pairs.
+    pairs: bool,
+}
+
+struct Renderer<'a, 'f, 'w> {
+    bytes: &'a [u8],
+    options: &'a RenderOptions,
+    /// Offsets of the maps to write as `$map`, see `find_pair_maps`.
+    pair_maps: HashSet<usize>,
+    f: &'f mut std::fmt::Formatter<'w>,
+}
+
+impl Renderer<'_, '_, '_> {
+    fn diagnostic(&self) -> bool {
+        self.options.format == RenderFormat::Diagnostic
+    }
+
+    /// Writes the value at the start of the bytes.
+    ///
+    /// This doesn't recurse so it is safe to use on arbitrarily deeply nested untrusted input.
+    fn render(&mut self) -> Result<(), RenderError> {
+        let mut stack: Vec<Frame> = Vec::new();
+        let mut pos = 0;
+        loop {
+            if let Some(frame) = stack.last() {
+                self.separator(frame, stack.len())?;
+            }
+            let token = read_token(self.bytes, pos)?;
+            pos = token.end();
+            match token.kind {
+                Kind::Array(0) => self.f.write_str("[]")?,
+                Kind::Map(0) => self.f.write_str("{}")?,
+                Kind::Array(_) | Kind::Map(_) => {
+                    let is_map = matches!(token.kind, Kind::Map(_));
+                    let pairs = self.pair_maps.contains(&token.offset);
+                    self.f.write_str(match (is_map, pairs) {
+                        (true, true) => "{\"$map\":[",
+                        (true, false) => "{",
+                        (false, _) => "[",
+                    })?;
+                    stack.push(Frame {
+                        items: token.items(),
+                        written: 0,
+                        is_map,
+                        pairs,
+                    });
+                    continue;
+                }
+                _ => self.scalar(&token)?,
+            }
+
+            // The token is a complete item so close every container it completes.
+            while let Some(frame) = stack.last_mut() {
+                frame.written += 1;
+                if frame.pairs && frame.written % 2 == 0 {
+                    self.f.write_char(']')?;
+                }
+                if frame.written < frame.items {
+                    break;
+                }
+                let (is_map, pairs) = (frame.is_map, frame.pairs);
+                stack.pop();
+                self.newline(stack.len())?;
+                self.f.write_str(match (is_map, pairs) {
+                    (true, true) => "]}",
+                    (true, false) => "}",
+                    (false, _) => "]",
+                })?;
+            }
+            if stack.is_empty() {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Whatever goes between the previous item in a container and the next one.
+    fn separator(&mut self, frame: &Frame, depth: usize) -> Result<(), RenderError> {
+        if frame.pairs && frame.written % 2 == 1 {
+            self.f.write_char(',')?;
+            return Ok(());
+        }
+        if frame.is_map && frame.written % 2 == 1 {
+            self.f
+                .write_str(if self.options.pretty || self.diagnostic() {
+                    ": "
+                } else {
+                    ":"
+                })?;
+            return Ok(());
+        }
+        if frame.written > 0 {
+            self.f.write_char(',')?;
+            if !self.options.pretty && self.diagnostic() {
+                self.f.write_char(' ')?;
+            }
+        }
+        self.newline(depth)?;
+        if frame.pairs {
+            self.f.write_char('[')?;
+        }
+        Ok(())
+    }
+
+    fn newline(&mut self, depth: usize) -> Result<(), RenderError> {
+        if self.options.pretty {
+            self.f.write_char('\n')?;
+            for _ in 0..depth {
+                self.f.write_str("  ")?;
+            }
+        }
+        Ok(())
+    }
+
+    fn scalar(&mut self, token: &Token) -> Result<(), RenderError> {
+        let diagnostic = self.diagnostic();
+        match token.kind {
+            Kind::Nil => self.f.write_str("null")?,
+            Kind::Bool(b) => write!(self.f, "{}", b)?,
+            Kind::UInt(n) => {
+                write!(self.f, "{}", n)?;
+                if diagnostic {
+                    self.f.write_str(width_tag(token))?;
+                }
+            }
+            Kind::Int(n) => {
+                write!(self.f, "{}", n)?;
+                if diagnostic {
+                    self.f.write_str(width_tag(token))?;
+                }
+            }
+            Kind::F32(n) if diagnostic => write!(self.f, "{:?}_f32", n)?,
+            Kind::F64(n) if diagnostic => write!(self.f, "{:?}_f64", n)?,
+            Kind::F32(n) if n.is_finite() => write!(self.f, "{:?}", n)?,
+            Kind::F64(n) if n.is_finite() => write!(self.f, "{:?}", n)?,
+            Kind::F32(_) | Kind::F64(_) => self.f.write_str("null")?,
+            Kind::Str(_) => {
+                let payload = token.payload(self.bytes);
+                match std::str::from_utf8(payload) {
+                    Ok(s) => self.string(s)?,
+                    Err(_) if diagnostic => {
+                        self.f.write_str("str(")?;
+                        self.payload(payload, BinFormat::Hex)?;
+                        self.f.write_char(')')?;
+                    }
+                    Err(_) => self.string(&String::from_utf8_lossy(payload))?,
+                }
+            }
+            Kind::Bin(_) => self.payload(token.payload(self.bytes), self.options.bin)?,
+            Kind::Ext(ty, _) => {
+                if diagnostic {
+                    write!(self.f, "ext({}, ", ty)?;
+                } else {
+                    write!(self.f, "{{\"$ext\":[{},", ty)?;
+                }
+                self.payload(token.payload(self.bytes), self.options.bin)?;
+                self.f.write_str(if diagnostic { ")" } else { "]}" })?;
+            }
+            Kind::Array(_) | Kind::Map(_) => unreachable!("containers are rendered by render"),
+        }
+        Ok(())
+    }
+
+    fn string(&mut self, s: &str) -> Result<(), RenderError> {
+        // serde_json can't fail to serialize a str
+        let escaped = serde_json::to_string(s).map_err(|_| RenderError::Fmt)?;
+        self.f.write_str(&escaped)?;
+        Ok(())
+    }
+
+    fn payload(&mut self, payload: &[u8], format: BinFormat) -> Result<(), RenderError> {
+        let diagnostic = self.diagnostic();
+        let shown = &payload[..payload.len().min(self.options.max_bin_len)];
+        match (format, diagnostic) {
+            (BinFormat::Array, _) => {
+                self.f.write_str(if diagnostic { "bin[" } else { "[" })?;
+                for (i, b) in shown.iter().enumerate() {
+                    if i > 0 {
+                        self.f.write_str(if diagnostic { ", " } else { "," })?;
+                    }
+                    write!(self.f, "{}", b)?;
+                }
+                if shown.len() < payload.len() && !shown.is_empty() {
+                    self.f.write_str(if diagnostic { ", " } else { "," })?;
+                }
+            }
+            (BinFormat::Hex, true) => self.f.write_str("h'")?,
+            (BinFormat::Base64, true) => self.f.write_str("b64'")?,
+            (_, false) => self.f.write_char('"')?,
+        }
+        match format {
+            BinFormat::Array => {}
+            BinFormat::Hex => {
+                for b in shown {
+                    write!(self.f, "{:02x}", b)?;
+                }
+            }
+            BinFormat::Base64 => self
+                .f
+                .write_str(&base64::engine::general_purpose::STANDARD.encode(shown))?,
+        }
+        if shown.len() < payload.len() {
+            // a JSON array of bytes can only hold the count as a string
+            let quote = if format == BinFormat::Array && !diagnostic {
+                "\""
+            } else {
+                ""
+            };
+            write!(
+                self.f,
+                "{}…(+{} bytes){}",
+                quote,
+                payload.len() - shown.len(),
+                quote
+            )?;
+        }
+        match (format, diagnostic) {
+            (BinFormat::Array, _) => self.f.write_char(']')?,
+            (_, true) => self.f.write_char('\'')?,
+            (_, false) => self.f.write_char('"')?,
+        }
+        Ok(())
+    }
+}
+
+/// Adds the offset of every map with a key that isn't a str to `maps` and returns the length of
+/// the value. JSON object keys can only be strings so these maps are written as `$map`, which
+/// has to be known before the first key is written.
+fn find_pair_maps(bytes: &[u8], maps: &mut HashSet<usize>) -> Result<usize, ReadError> {
+    // The offset, whether it is a map and the number of child items so far of every container
+    // enclosing the current token.
+    let mut stack: Vec<(usize, bool, usize)> = Vec::new();
+    walk(bytes, 0, |event| {
+        if let Event::Token { token, depth } = event {
+            stack.truncate(depth);
+            if let Some((offset, is_map, items)) = stack.last_mut() {
+                if *is_map && *items % 2 == 0 && !matches!(token.kind, Kind::Str(_)) {
+                    maps.insert(*offset);
+                }
+                *items += 1;
+            }
+            if token.items() > 0 {
+                stack.push((token.offset, matches!(token.kind, Kind::Map(_)), 0));
+            }
+        }
+        Ok::<_, ReadError>(())
+    })
+}
+
+/// The diagnostic tag for the marker an integer was written with, nothing for fixints.
+fn width_tag(token: &Token) -> &'static str {
+    match (token.kind, token.header_len) {
+        (Kind::UInt(_), 2) => "_u8",
+        (Kind::UInt(_), 3) => "_u16",
+        (Kind::UInt(_), 5) => "_u32",
+        (Kind::UInt(_), 9) => "_u64",
+        (Kind::Int(_), 2) => "_i8",
+        (Kind::Int(_), 3) => "_i16",
+        (Kind::Int(_), 5) => "_i32",
+        (Kind::Int(_), 9) => "_i64",
+        _ => "",
+    }
+}
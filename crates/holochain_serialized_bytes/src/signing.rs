@@ -0,0 +1,134 @@
+//! Signatures over serialized bytes.
+//!
+//! A signature is only meaningful for the exact bytes that were signed. Re-encoding a decoded
+//! value can produce different bytes (e.g. a newer version of a type, or a `HashMap` in a
+//! different order), so `Signed<T>` keeps the signed bytes themselves and only ever decodes `T`
+//! from those bytes after checking the signature.
+
+use crate::SerializedBytes;
+use crate::SerializedBytesError;
+
+/// Anything that can sign bytes, e.g. a private key or a handle to a keystore.
+pub trait Signer {
+    fn sign(&self, bytes: &[u8]) -> Result<Vec<u8>, SerializedBytesError>;
+}
+
+/// Anything that can check a signature over bytes, e.g. a public key.
+pub trait Verifier {
+    /// `SerializedBytesError::Signature` unless `signature` is a valid signature of `bytes`.
+    fn verify(&self, bytes: &[u8], signature: &[u8]) -> Result<(), SerializedBytesError>;
+}
+
+/// The `SerializedBytes` of a `T` along with a signature over exactly those bytes.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Signed<T> {
+    bytes: SerializedBytes,
+    #[serde(with = "serde_bytes")]
+    signature: Vec<u8>,
+    #[serde(skip)]
+    content: std::marker::PhantomData<fn() -> T>,
+}
+
+impl<T> Signed<T> {
+    /// Sign the `holochain_serial!` bytes of `content` exactly as they are, so the signed bytes
+    /// are the ones `content_hash` hashes, and types encoded `with` something other than
+    /// messagepack can be signed too.
+    pub fn sign<S: Signer>(content: &T, signer: &S) -> Result<Self, SerializedBytesError>
+    where
+        for<'a> SerializedBytes: TryFrom<&'a T, Error = SerializedBytesError>,
+    {
+        let bytes = SerializedBytes::try_from(content)?;
+        let signature = signer.sign(bytes.bytes())?;
+        Ok(Self::from_parts(bytes, signature))
+    }
+
+    /// Put together signed bytes and their signature, e.g. as received from elsewhere.
+    /// Nothing is checked until `verify`.
+    pub fn from_parts(bytes: SerializedBytes, signature: Vec<u8>) -> Self {
+        Self {
+            bytes,
+            signature,
+            content: std::marker::PhantomData,
+        }
+    }
+
+    /// Check the signature over the stored bytes then decode `T` from those same bytes.
+    pub fn verify<V: Verifier>(&self, verifier: &V) -> Result<T, SerializedBytesError>
+    where
+        T: TryFrom<SerializedBytes, Error = SerializedBytesError>,
+    {
+        verifier.verify(self.bytes.bytes(), &self.signature)?;
+        T::try_from(self.bytes.clone())
+    }
+
+    /// The signed bytes, which have not necessarily been verified.
+    pub fn bytes(&self) -> &SerializedBytes {
+        &self.bytes
+    }
+
+    pub fn signature(&self) -> &[u8] {
+        &self.signature
+    }
+
+    pub fn into_parts(self) -> (SerializedBytes, Vec<u8>) {
+        (self.bytes, self.signature)
+    }
+}
+
+// Manual impls so that none of them need anything from `T`.
+impl<T> Clone for Signed<T> {
+    fn clone(&self) -> Self {
+        Self::from_parts(self.bytes.clone(), self.signature.clone())
+    }
+}
+
+impl<T> std::fmt::Debug for Signed<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Signed")
+            .field("bytes", &self.bytes)
+            .field("signature", &self.signature)
+            .finish()
+    }
+}
+
+impl<T> PartialEq for Signed<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.bytes == other.bytes && self.signature == other.signature
+    }
+}
+
+impl<T> Eq for Signed<T> {}
+
+impl<T> std::hash::Hash for Signed<T> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.bytes.hash(state);
+        self.signature.hash(state);
+    }
+}
+
+// No `T` bounds, the content is only ever handled as bytes.
+crate::holochain_serial!(@impl [crate::encode] [crate::SerializedBytesError] [T] [Signed<T>] []);
+
+#[cfg(feature = "ed25519")]
+impl Signer for ed25519_dalek::SigningKey {
+    fn sign(&self, bytes: &[u8]) -> Result<Vec<u8>, SerializedBytesError> {
+        use ed25519_dalek::Signer;
+        Ok(self.try_sign(bytes).map_err(signature_error)?.to_vec())
+    }
+}
+
+#[cfg(feature = "ed25519")]
+impl Verifier for ed25519_dalek::VerifyingKey {
+    fn verify(&self, bytes: &[u8], signature: &[u8]) -> Result<(), SerializedBytesError> {
+        let signature = ed25519_dalek::Signature::from_slice(signature).map_err(signature_error)?;
+        self.verify_strict(bytes, &signature)
+            .map_err(signature_error)
+    }
+}
+
+#[cfg(feature = "ed25519")]
+fn signature_error(err: ed25519_dalek::SignatureError) -> SerializedBytesError {
+    #[cfg(feature = "trace")]
+    tracing::warn!("Signature error: {}", err);
+    SerializedBytesError::Signature(err.to_string())
+}
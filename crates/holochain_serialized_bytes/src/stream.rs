@@ -0,0 +1,249 @@
+//! Encoding to and decoding from anything `std::io` rather than a single in memory buffer.
+//!
+//! The bytes written are exactly the bytes `encode` returns.
+
+use crate::limits;
+use crate::msgpack::{read_head, ReadError, Token};
+use crate::proxy;
+use crate::serialize_into;
+use crate::DecodeLimits;
+use crate::DeserializeError;
+use crate::LimitExceeded;
+use crate::SerializedBytesError;
+use serde::de::DeserializeOwned;
+use serde_path_to_error::Track;
+use std::io::{Read, Write};
+
+/// Encode `val` straight into `writer`, e.g. a file or socket.
+///
+/// Nothing is buffered so wrap unbuffered writers in a `std::io::BufWriter`.
+#[cfg_attr(feature = "trace", tracing::instrument(skip(writer)))]
+pub fn encode_into<T, W>(val: &T, writer: W) -> Result<(), SerializedBytesError>
+where
+    T: serde::Serialize + std::fmt::Debug,
+    W: Write,
+{
+    serialize_into(val, writer)?;
+    #[cfg(feature = "trace")]
+    tracing::trace!(
+        "Serialized {} input into writer",
+        std::any::type_name::<T>()
+    );
+    Ok(())
+}
+
+/// Encode `val` onto the end of `buf`, reusing its spare capacity.
+///
+/// If encoding fails `buf` is left as it was.
+#[cfg_attr(feature = "trace", tracing::instrument(skip(buf)))]
+pub fn encode_into_vec<T>(val: &T, buf: &mut Vec<u8>) -> Result<(), SerializedBytesError>
+where
+    T: serde::Serialize + std::fmt::Debug,
+{
+    let len = buf.len();
+    if let Err(err) = serialize_into(val, &mut *buf) {
+        buf.truncate(len);
+        return Err(err);
+    }
+    #[cfg(feature = "trace")]
+    tracing::trace!(
+        "Serialized {} input into {:?}",
+        std::any::type_name::<T>(),
+        &buf[len..]
+    );
+    Ok(())
+}
+
+/// The number of bytes `encode` would return for `val`, without allocating them.
+///
+/// e.g. to allocate exactly enough wasm guest memory before encoding into it.
+#[cfg_attr(feature = "trace", tracing::instrument)]
+pub fn encoded_len<T>(val: &T) -> Result<usize, SerializedBytesError>
+where
+    T: serde::Serialize + std::fmt::Debug,
+{
+    serialize_into(val, CountingWriter(0)).map(|counter| counter.0)
+}
+
+/// Decode one messagepack value from `reader`, e.g. a file or socket.
+///
+/// Only the bytes of the value are read, so the reader is left at the start of whatever follows
+/// it and this can be called repeatedly to read a stream of values.
+///
+/// There are no resource limits, use `decode_from_with_limits` for untrusted input.
+#[cfg_attr(feature = "trace", tracing::instrument(skip(reader)))]
+pub fn decode_from<T, R>(reader: R) -> Result<T, SerializedBytesError>
+where
+    T: DeserializeOwned + std::fmt::Debug,
+    R: Read,
+{
+    read_value(reader, None)
+}
+
+/// Decode one messagepack value from `reader`, the same as `decode_from`, but refuse to
+/// deserialize anything that exceeds the given limits.
+///
+/// The value can't be checked before it is read, so instead every header is checked as it is
+/// read, before the payload or items it declares.
+#[cfg_attr(feature = "trace", tracing::instrument(skip(reader)))]
+pub fn decode_from_with_limits<T, R>(
+    reader: R,
+    limits: &DecodeLimits,
+) -> Result<T, SerializedBytesError>
+where
+    T: DeserializeOwned + std::fmt::Debug,
+    R: Read,
+{
+    read_value(reader, Some(limits))
+}
+
+fn read_value<T, R>(reader: R, limits: Option<&DecodeLimits>) -> Result<T, SerializedBytesError>
+where
+    T: DeserializeOwned + std::fmt::Debug,
+    R: Read,
+{
+    // Unlike a slice the input can't be read a second time to explain an error, so the path is
+    // always tracked. The offset is as far as the deserializer got.
+    let state = proxy::State::default();
+    let mut track = Track::new();
+    let mut deserializer = rmp_serde::Deserializer::new(CheckingReader {
+        inner: reader,
+        offset: 0,
+        limits: limits.map(|limits| Check {
+            limits,
+            head: Vec::new(),
+            payload: 0,
+            stack: Vec::new(),
+            alloc_bytes: 0,
+            exceeded: None,
+        }),
+    });
+    let ret = T::deserialize(serde_path_to_error::Deserializer::new(
+        proxy::Deserializer::new(&mut deserializer, &state),
+        &mut track,
+    ))
+    .map_err(|err| {
+        #[cfg(feature = "trace")]
+        tracing::warn!(
+            "Failed to deserialize input into: {}",
+            std::any::type_name::<T>()
+        );
+        let reader = deserializer.get_ref();
+        match reader.limits.as_ref().and_then(|check| check.exceeded) {
+            Some(exceeded) => SerializedBytesError::LimitExceeded(exceeded),
+            None => SerializedBytesError::Deserialize(DeserializeError::from_rmp(
+                &err,
+                &track.path(),
+                reader.offset,
+                std::any::type_name::<T>(),
+            )),
+        }
+    })?;
+    #[cfg(feature = "trace")]
+    tracing::trace!("Deserialized input into: {:?}", ret);
+    Ok(ret)
+}
+
+/// Counts the bytes the deserializer reads and checks them against any limits on the way past.
+struct CheckingReader<'l, R> {
+    inner: R,
+    offset: usize,
+    limits: Option<Check<'l>>,
+}
+
+impl<R: Read> Read for CheckingReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if let Some(check) = &mut self.limits {
+            check.bytes(&buf[..n], self.offset)?;
+        }
+        self.offset += n;
+        Ok(n)
+    }
+}
+
+/// Follows the tokens in the bytes read so far.
+struct Check<'l> {
+    limits: &'l DecodeLimits,
+    /// the marker and header read so far of the token being read
+    head: Vec<u8>,
+    /// payload bytes left to read of the current str, bin or ext
+    payload: usize,
+    /// items left to read of every array and map the current token is inside
+    stack: Vec<usize>,
+    alloc_bytes: usize,
+    exceeded: Option<LimitExceeded>,
+}
+
+impl Check<'_> {
+    /// Follow `bytes` starting at `offset` in the stream, and refuse them once a header exceeds
+    /// the limits.
+    fn bytes(&mut self, mut bytes: &[u8], mut offset: usize) -> std::io::Result<()> {
+        while let Some((&byte, rest)) = bytes.split_first() {
+            if self.payload > 0 {
+                let n = self.payload.min(bytes.len());
+                self.payload -= n;
+                offset += n;
+                bytes = &bytes[n..];
+                if self.payload == 0 {
+                    self.item_done();
+                }
+                continue;
+            }
+            self.head.push(byte);
+            offset += 1;
+            bytes = rest;
+            let token = match read_head(&self.head, 0) {
+                Ok(token) => Token {
+                    offset: offset - self.head.len(),
+                    ..token
+                },
+                Err(ReadError::UnexpectedEof { .. }) => continue,
+                // the deserializer reports anything that isn't messagepack
+                Err(_) => {
+                    self.head.clear();
+                    continue;
+                }
+            };
+            self.head.clear();
+            if let Err(exceeded) =
+                limits::check(&token, self.stack.len(), self.limits, &mut self.alloc_bytes)
+            {
+                self.exceeded = Some(exceeded);
+                return Err(std::io::Error::other(exceeded.to_string()));
+            }
+            self.payload = token.payload_len();
+            if token.items() > 0 {
+                self.stack.push(token.items());
+            } else if self.payload == 0 {
+                self.item_done();
+            }
+        }
+        Ok(())
+    }
+
+    /// Count a complete item against the arrays and maps it completes.
+    fn item_done(&mut self) {
+        while let Some(items) = self.stack.last_mut() {
+            *items -= 1;
+            if *items > 0 {
+                break;
+            }
+            self.stack.pop();
+        }
+    }
+}
+
+/// Discards everything written to it other than how many bytes there were.
+struct CountingWriter(usize);
+
+impl Write for CountingWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,320 @@
+//! A dynamic view of serialized bytes, for tooling and logging that need to look inside bytes
+//! without the type that wrote them.
+//!
+//! Unlike e.g. `serde_json::Value` nothing messagepack distinguishes is lost: bin and str are
+//! different, as are unsigned and signed integers and f32 and f64, and ext values are kept with
+//! their type.
+
+use crate::msgpack::{read_token, skip, Kind, ReadError, Token};
+use crate::DeserializeError;
+use crate::DeserializeErrorKind;
+use crate::SerializedBytesError;
+use serde::Deserialize;
+
+/// Any messagepack value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    /// Written with a positive fixint or uint marker.
+    UInt(u64),
+    /// Written with a negative fixint or int marker.
+    Int(i64),
+    F32(f32),
+    F64(f64),
+    Str(String),
+    Bin(Vec<u8>),
+    Array(Vec<Value>),
+    /// Entries in the order they were written, keys can be any value.
+    Map(Vec<(Value, Value)>),
+    /// Ext type and data.
+    Ext(i8, Vec<u8>),
+}
+
+impl Value {
+    pub fn is_nil(&self) -> bool {
+        matches!(self, Value::Nil)
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    /// Any integer that fits in a `u64`, whatever marker it was written with.
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Value::UInt(n) => Some(*n),
+            Value::Int(n) => u64::try_from(*n).ok(),
+            _ => None,
+        }
+    }
+
+    /// Any integer that fits in an `i64`, whatever marker it was written with.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Value::UInt(n) => i64::try_from(*n).ok(),
+            Value::Int(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    /// Either float.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::F32(n) => Some((*n).into()),
+            Value::F64(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_bin(&self) -> Option<&[u8]> {
+        match self {
+            Value::Bin(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn as_map(&self) -> Option<&[(Value, Value)]> {
+        match self {
+            Value::Map(entries) => Some(entries),
+            _ => None,
+        }
+    }
+}
+
+/// The value of the complete messagepack value in `bytes`.
+pub(crate) fn to_value(bytes: &[u8]) -> Result<Value, SerializedBytesError> {
+    let (value, len) = read_value(bytes, 0).map_err(SerializedBytesError::Deserialize)?;
+    if len != bytes.len() {
+        return Err(SerializedBytesError::Deserialize(DeserializeError {
+            kind: DeserializeErrorKind::TrailingBytes,
+            offset: Some(len),
+            type_name: std::any::type_name::<Value>().to_string(),
+            path: String::new(),
+            message: format!("{} trailing bytes after value", bytes.len() - len),
+        }));
+    }
+    Ok(value)
+}
+
+/// The value at `path` in `bytes`, a `.` separated list of map keys and array indexes.
+pub(crate) fn get(bytes: &[u8], path: &str) -> Result<Option<Value>, SerializedBytesError> {
+    let segments = path.split('.').filter(|_| !path.is_empty());
+    let error = |mut err: DeserializeError| {
+        err.path = path.to_string();
+        SerializedBytesError::Deserialize(err)
+    };
+    match find(bytes, segments).map_err(|err| error(from_read(err)))? {
+        Some(offset) => read_value(bytes, offset)
+            .map(|(value, _)| Some(value))
+            .map_err(error),
+        None => Ok(None),
+    }
+}
+
+/// The `T` at `path` in `bytes`, decoded from only the bytes of that value.
+///
+/// Errors have the offset in `bytes` and the full path, from the root rather than the field.
+pub(crate) fn field<'a, T>(
+    bytes: &'a [u8],
+    path: &[&str],
+) -> Result<Option<T>, SerializedBytesError>
+where
+    T: Deserialize<'a> + std::fmt::Debug,
+{
+    let error = |mut err: DeserializeError, offset: usize| {
+        err.offset = err.offset.map(|o| o + offset);
+        err.path = path
+            .iter()
+            .copied()
+            .chain(Some(err.path.as_str()).filter(|p| !p.is_empty()))
+            .collect::<Vec<_>>()
+            .join(".");
+        SerializedBytesError::Deserialize(err)
+    };
+    let offset = match find(bytes, path.iter().copied()) {
+        Ok(Some(offset)) => offset,
+        Ok(None) => return Ok(None),
+        Err(err) => {
+            return Err(error(
+                DeserializeError::from_read(err, std::any::type_name::<T>()),
+                0,
+            ))
+        }
+    };
+    match crate::decode_prefix(&bytes[offset..]) {
+        Ok((value, _)) => Ok(Some(value)),
+        Err(SerializedBytesError::Deserialize(err)) => Err(error(err, offset)),
+        Err(err) => Err(err),
+    }
+}
+
+/// Offset of the value at the end of `path`, reading only the bytes on the way to it.
+///
+/// `None` if anything on the path doesn't exist, e.g. a missing map key, an array index past the
+/// end or a key of something that is neither a map nor an array.
+/// Map keys match str keys, or integer keys if they are integers.
+pub(crate) fn find<'p>(
+    bytes: &[u8],
+    path: impl IntoIterator<Item = &'p str>,
+) -> Result<Option<usize>, ReadError> {
+    let mut pos = 0;
+    for segment in path {
+        let token = read_token(bytes, pos)?;
+        pos = token.end();
+        match token.kind {
+            Kind::Array(len) => match segment.parse::<usize>() {
+                Ok(index) if index < len => {
+                    for _ in 0..index {
+                        pos = skip(bytes, pos)?;
+                    }
+                }
+                _ => return Ok(None),
+            },
+            Kind::Map(len) => {
+                let mut found = false;
+                for _ in 0..len {
+                    let key = read_token(bytes, pos)?;
+                    pos = skip(bytes, pos)?;
+                    if key_matches(bytes, &key, segment) {
+                        found = true;
+                        break;
+                    }
+                    pos = skip(bytes, pos)?;
+                }
+                if !found {
+                    return Ok(None);
+                }
+            }
+            _ => return Ok(None),
+        }
+    }
+    Ok(Some(pos))
+}
+
+fn key_matches(bytes: &[u8], key: &Token, segment: &str) -> bool {
+    match key.kind {
+        Kind::Str(_) => key.payload(bytes) == segment.as_bytes(),
+        Kind::UInt(n) => segment.parse() == Ok(n),
+        Kind::Int(n) => segment.parse() == Ok(n),
+        _ => false,
+    }
+}
+
+fn from_read(err: ReadError) -> DeserializeError {
+    DeserializeError::from_read(err, std::any::type_name::<Value>())
+}
+
+/// How deeply rmp_serde, and so `decode`, nests arrays and maps, so anything `decode` accepts is
+/// also a `Value`.
+const MAX_DEPTH: usize = 1024;
+
+/// An array or map that hasn't had all its items read yet.
+enum Partial {
+    Array(Vec<Value>, usize),
+    Map(Vec<(Value, Value)>, Option<Value>, usize),
+}
+
+/// Reads the complete value starting at `offset` and returns it with the offset it ends at.
+///
+/// This doesn't recurse so it is safe to use on untrusted input. Dropping a `Value` does recurse
+/// though, so anything nested deeper than `MAX_DEPTH` is refused.
+fn read_value(bytes: &[u8], offset: usize) -> Result<(Value, usize), DeserializeError> {
+    let mut stack: Vec<Partial> = Vec::new();
+    let mut pos = offset;
+    loop {
+        let token = read_token(bytes, pos).map_err(from_read)?;
+        pos = token.end();
+        let payload = || token.payload(bytes).to_vec();
+        let mut value = match token.kind {
+            Kind::Nil => Value::Nil,
+            Kind::Bool(b) => Value::Bool(b),
+            Kind::UInt(n) => Value::UInt(n),
+            Kind::Int(n) => Value::Int(n),
+            Kind::F32(n) => Value::F32(n),
+            Kind::F64(n) => Value::F64(n),
+            Kind::Str(_) => {
+                Value::Str(
+                    String::from_utf8(payload()).map_err(|err| DeserializeError {
+                        kind: DeserializeErrorKind::InvalidUtf8,
+                        offset: Some(token.offset),
+                        type_name: std::any::type_name::<Value>().to_string(),
+                        path: String::new(),
+                        message: err.to_string(),
+                    })?,
+                )
+            }
+            Kind::Bin(_) => Value::Bin(payload()),
+            Kind::Ext(ty, _) => Value::Ext(ty, payload()),
+            Kind::Array(0) => Value::Array(Vec::new()),
+            Kind::Map(0) => Value::Map(Vec::new()),
+            Kind::Array(_) | Kind::Map(_) if stack.len() == MAX_DEPTH => {
+                return Err(DeserializeError {
+                    kind: DeserializeErrorKind::DepthExceeded,
+                    offset: Some(token.offset),
+                    type_name: std::any::type_name::<Value>().to_string(),
+                    path: String::new(),
+                    message: format!("arrays and maps nested more than {} deep", MAX_DEPTH),
+                })
+            }
+            // The lengths are only what the input claims, nested headers can each claim most of
+            // the input, so the items are collected as they are read rather than preallocated.
+            Kind::Array(len) => {
+                stack.push(Partial::Array(Vec::new(), len));
+                continue;
+            }
+            Kind::Map(len) => {
+                stack.push(Partial::Map(Vec::new(), None, len));
+                continue;
+            }
+        };
+
+        // The value is complete so add it to its container, and finish off every container
+        // that completes.
+        loop {
+            match stack.last_mut() {
+                None => return Ok((value, pos)),
+                Some(Partial::Array(items, len)) => {
+                    items.push(value);
+                    if items.len() < *len {
+                        break;
+                    }
+                }
+                Some(Partial::Map(entries, key, len)) => match key.take() {
+                    None => {
+                        *key = Some(value);
+                        break;
+                    }
+                    Some(key) => {
+                        entries.push((key, value));
+                        if entries.len() < *len {
+                            break;
+                        }
+                    }
+                },
+            }
+            value = match stack.pop() {
+                Some(Partial::Array(items, _)) => Value::Array(items),
+                Some(Partial::Map(entries, _, _)) => Value::Map(entries),
+                None => unreachable!("there was a container to add the value to"),
+            };
+        }
+    }
+}
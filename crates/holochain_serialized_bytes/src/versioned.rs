@@ -0,0 +1,176 @@
+//! Versioned envelopes, so stored bytes say which version of a type wrote them.
+//!
+//! Changing a shared type, or upgrading a dependency that changes how it serializes, leaves old
+//! bytes that may no longer decode or may decode into the wrong thing. With the version in the
+//! bytes, old data can be recognised and migrated to the current version of the type instead.
+
+use crate::DeserializeError;
+use crate::DeserializeErrorKind;
+use crate::SerializedBytes;
+use crate::SerializedBytesError;
+use std::collections::BTreeMap;
+
+/// The version of a type's serialized form.
+/// Increment it every time the serialized form changes.
+pub trait SchemaVersion {
+    const SCHEMA_VERSION: u32;
+}
+
+/// A `SchemaVersion` along with the `SerializedBytes` of that version of a type.
+///
+/// Serializes as `{"version": <u32>, "content": <bin>}`.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Versioned<T> {
+    version: u32,
+    content: SerializedBytes,
+    #[serde(skip)]
+    current: std::marker::PhantomData<fn() -> T>,
+}
+
+type Migration<T> = Box<dyn Fn(SerializedBytes) -> Result<T, SerializedBytesError> + Send + Sync>;
+
+/// How to get the current version of `T` from the content of older versions.
+pub struct Migrations<T> {
+    migrations: BTreeMap<u32, Migration<T>>,
+}
+
+impl<T> Default for Migrations<T> {
+    fn default() -> Self {
+        Self {
+            migrations: BTreeMap::new(),
+        }
+    }
+}
+
+impl<T> Migrations<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register the function that turns the content of `version` into the current `T`,
+    /// typically by decoding it as the old type and converting that.
+    pub fn register<F>(mut self, version: u32, migration: F) -> Self
+    where
+        F: Fn(SerializedBytes) -> Result<T, SerializedBytesError> + Send + Sync + 'static,
+    {
+        self.migrations.insert(version, Box::new(migration));
+        self
+    }
+
+    /// The versions there are migrations for.
+    pub fn versions(&self) -> impl Iterator<Item = u32> + '_ {
+        self.migrations.keys().copied()
+    }
+}
+
+impl<T> std::fmt::Debug for Migrations<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Migrations")
+            .field("versions", &self.migrations.keys())
+            .finish()
+    }
+}
+
+impl<T: SchemaVersion> Versioned<T> {
+    /// Wrap the `holochain_serial!` bytes of `content` with the current schema version of `T`.
+    pub fn new(content: &T) -> Result<Self, SerializedBytesError>
+    where
+        for<'a> SerializedBytes: TryFrom<&'a T, Error = SerializedBytesError>,
+    {
+        Ok(Self::from_parts(
+            T::SCHEMA_VERSION,
+            SerializedBytes::try_from(content)?,
+        ))
+    }
+
+    /// Decode the content as the current version of `T`, migrating it if it was written by an
+    /// older version.
+    pub fn decode(self, migrations: &Migrations<T>) -> Result<T, SerializedBytesError>
+    where
+        T: TryFrom<SerializedBytes, Error = SerializedBytesError>,
+    {
+        if self.version == T::SCHEMA_VERSION {
+            return T::try_from(self.content);
+        }
+        match migrations.migrations.get(&self.version) {
+            Some(migration) if self.version < T::SCHEMA_VERSION => migration(self.content),
+            _ => {
+                #[cfg(feature = "trace")]
+                tracing::warn!(
+                    "No migration for {} from version {}",
+                    std::any::type_name::<T>(),
+                    self.version
+                );
+                Err(SerializedBytesError::Deserialize(DeserializeError {
+                    kind: DeserializeErrorKind::UnsupportedVersion,
+                    offset: None,
+                    type_name: std::any::type_name::<T>().to_string(),
+                    path: String::new(),
+                    message: format!(
+                        "no migration from version {} to version {}",
+                        self.version,
+                        T::SCHEMA_VERSION
+                    ),
+                }))
+            }
+        }
+    }
+}
+
+impl<T> Versioned<T> {
+    /// Put together content and the version it was written with.
+    /// This is how to write the content of an older version, e.g. in tests of a migration.
+    pub fn from_parts(version: u32, content: SerializedBytes) -> Self {
+        Self {
+            version,
+            content,
+            current: std::marker::PhantomData,
+        }
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn content(&self) -> &SerializedBytes {
+        &self.content
+    }
+
+    pub fn into_parts(self) -> (u32, SerializedBytes) {
+        (self.version, self.content)
+    }
+}
+
+// Manual impls so that none of them need anything from `T`.
+impl<T> Clone for Versioned<T> {
+    fn clone(&self) -> Self {
+        Self::from_parts(self.version, self.content.clone())
+    }
+}
+
+impl<T> std::fmt::Debug for Versioned<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Versioned")
+            .field("version", &self.version)
+            .field("content", &self.content)
+            .finish()
+    }
+}
+
+impl<T> PartialEq for Versioned<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.version == other.version && self.content == other.content
+    }
+}
+
+impl<T> Eq for Versioned<T> {}
+
+impl<T> std::hash::Hash for Versioned<T> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.version.hash(state);
+        self.content.hash(state);
+    }
+}
+
+// No `T` bounds, the content is only ever handled as bytes.
+crate::holochain_serial!(@impl [crate::encode] [crate::SerializedBytesError] [T] [Versioned<T>] []);
@@ -5,21 +5,187 @@
 extern crate quote;
 
 use proc_macro::TokenStream;
+use syn::spanned::Spanned;
 
-fn impl_default_serialized_bytes_macro(ast: &syn::DeriveInput) -> TokenStream {
+fn impl_default_serialized_bytes_macro(ast: &syn::DeriveInput) -> syn::Result<TokenStream> {
     let name = &ast.ident;
-    let gen = quote! {
-        ::holochain_serialized_bytes::prelude::holochain_serial!(#name);
+
+    // serde can't (de)serialize unions so neither can we
+    if let syn::Data::Union(data) = &ast.data {
+        return Err(syn::Error::new_spanned(
+            data.union_token,
+            "SerializedBytes can't be derived for unions, use a struct or enum",
+        ));
+    }
+
+    let attributes = Attributes::parse(ast)?;
+
+    // impl generics can't have defaults
+    let mut generics = ast.generics.clone();
+    for param in generics.params.iter_mut() {
+        match param {
+            syn::GenericParam::Type(t) => {
+                t.eq_token = None;
+                t.default = None;
+            }
+            syn::GenericParam::Const(c) => {
+                c.eq_token = None;
+                c.default = None;
+            }
+            syn::GenericParam::Lifetime(_) => {}
+        }
+    }
+    let params = &generics.params;
+    let (_, ty_generics, where_clause) = ast.generics.split_for_impl();
+
+    let mut predicates: Vec<syn::WherePredicate> = where_clause
+        .map(|w| w.predicates.iter().cloned().collect())
+        .unwrap_or_default();
+
+    // Check the types have everything the impls need, with errors pointing at the types rather
+    // than somewhere inside `holochain_serial!`.
+    // The same bounds on the impls stop them from also failing to compile, so a missing trait is
+    // reported once, here. The bounds are higher ranked as otherwise a bound that doesn't hold for
+    // a type without generics is itself an error.
+    let mut assertions = vec![];
+    let mut asserted: Vec<syn::WherePredicate> = vec![];
+
+    let error = match &attributes.error {
+        Some(error) => {
+            assertions.push(quote_spanned! {error.span()=>
+                error_requires::<#error>();
+            });
+            asserted.push(syn::parse_quote! {
+                for<'__check> #error: ::std::convert::From<
+                    ::holochain_serialized_bytes::prelude::SerializedBytesError
+                >
+            });
+            quote! { #error }
+        }
+        None => quote! { ::holochain_serialized_bytes::prelude::SerializedBytesError },
+    };
+
+    let serial = match &attributes.with {
+        // custom encodings are all up to the module, nothing needs to be serde
+        Some(with) => quote! { @impl with [#with] },
+        None => {
+            // every type parameter needs to make it in and out of messagepack
+            for t in ast.generics.type_params() {
+                let ident = &t.ident;
+                predicates.push(syn::parse_quote! {
+                    #ident: ::holochain_serialized_bytes::prelude::Serialize
+                        + for<'__de> ::holochain_serialized_bytes::prelude::Deserialize<'__de>
+                        + ::std::fmt::Debug
+                });
+            }
+            let ty = quote_spanned! {name.span()=> #name #ty_generics };
+            assertions.push(quote_spanned! {name.span()=>
+                serialized_bytes_requires::<#ty>();
+            });
+            asserted.push(syn::parse_quote! {
+                for<'__check> #ty: ::holochain_serialized_bytes::prelude::Serialize
+                    + ::std::fmt::Debug
+            });
+
+            if attributes.compact {
+                quote! { @impl [::holochain_serialized_bytes::prelude::encode_compact] }
+            } else {
+                quote! { @impl [::holochain_serialized_bytes::prelude::encode] }
+            }
+        }
     };
-    gen.into()
+
+    Ok(quote! {
+        const _: () = {
+            #[allow(dead_code)]
+            fn serialized_bytes_requires<'__de, T>()
+            where
+                T: ::holochain_serialized_bytes::prelude::Serialize
+                    + ::holochain_serialized_bytes::prelude::Deserialize<'__de>
+                    + ::std::fmt::Debug,
+            {
+            }
+
+            #[allow(dead_code)]
+            fn error_requires<E>()
+            where
+                E: ::std::convert::From<::holochain_serialized_bytes::prelude::SerializedBytesError>,
+            {
+            }
+
+            #[allow(dead_code)]
+            fn check<#params>()
+            where
+                #(#predicates,)*
+            {
+                #(#assertions)*
+            }
+        };
+
+        ::holochain_serialized_bytes::prelude::holochain_serial!(
+            #serial [#error] [#params] [#name #ty_generics] [#(#predicates,)* #(#asserted,)*]
+        );
+    }
+    .into())
+}
+
+/// Everything that can go in `#[serialized_bytes(...)]`.
+#[derive(Default)]
+struct Attributes {
+    /// `compact`, the fields of the type are encoded as an array rather than a map.
+    compact: bool,
+    /// `with = "path::to::module"`, the module has `to_bytes` and `from_bytes` functions that
+    /// replace messagepack entirely.
+    with: Option<syn::Path>,
+    /// `error = "MyError"`, the `Error` of the `TryFrom` impls rather than `SerializedBytesError`.
+    error: Option<syn::Type>,
+}
+
+impl Attributes {
+    fn parse(ast: &syn::DeriveInput) -> syn::Result<Self> {
+        let mut attributes = Self::default();
+        for attr in ast.attrs.iter() {
+            if !attr.path().is_ident("serialized_bytes") {
+                continue;
+            }
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("compact") {
+                    attributes.compact = true;
+                } else if meta.path.is_ident("with") {
+                    if attributes.with.is_some() {
+                        return Err(meta.error("duplicate `with`, a type has only one encoding"));
+                    }
+                    let module: syn::LitStr = meta.value()?.parse()?;
+                    attributes.with = Some(module.parse()?);
+                } else if meta.path.is_ident("error") {
+                    if attributes.error.is_some() {
+                        return Err(meta.error("duplicate `error`, the impls have only one error type"));
+                    }
+                    let error: syn::LitStr = meta.value()?.parse()?;
+                    attributes.error = Some(error.parse()?);
+                } else {
+                    return Err(meta.error(
+                        "unsupported serialized_bytes attribute, expected `compact`, `with` or `error`",
+                    ));
+                }
+                if attributes.compact && attributes.with.is_some() {
+                    return Err(meta.error(
+                        "`compact` has no effect on a `with` encoding, use one or the other",
+                    ));
+                }
+                Ok(())
+            })?;
+        }
+        Ok(attributes)
+    }
 }
 
-#[proc_macro_derive(SerializedBytes)]
+#[proc_macro_derive(SerializedBytes, attributes(serialized_bytes))]
 pub fn default_holochain_serialized_bytes_derive(input: TokenStream) -> TokenStream {
     // Construct a representation of Rust code as a syntax tree
     // that we can manipulate
-    let ast = syn::parse(input).unwrap();
+    let ast = syn::parse_macro_input!(input as syn::DeriveInput);
 
-    // Build the trait implementation
-    impl_default_serialized_bytes_macro(&ast)
+    // Build the trait implementation, or errors pointing at what can't be derived
+    impl_default_serialized_bytes_macro(&ast).unwrap_or_else(|err| err.to_compile_error().into())
 }
@@ -91,6 +91,79 @@ pub fn round_nested(c: &mut Criterion) {
     group.finish();
 }
 
-criterion_group!(bench, round_trip_string, round_trip_bytes, round_nested);
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct AgentKey(#[serde(with = "serde_bytes")] Vec<u8>);
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct Header {
+    seq: u32,
+    author: AgentKey,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, SerializedBytes)]
+struct Entry {
+    #[serde(with = "serde_bytes")]
+    payload: Vec<u8>,
+    header: Header,
+}
+
+pub fn field_access(c: &mut Criterion) {
+    let mut group = c.benchmark_group("field_access");
+
+    for n in [0, 1, 1_000, 1_000_000] {
+        let sb = SerializedBytes::try_from(Entry {
+            payload: vec![0_u8; n],
+            header: Header {
+                seq: 1,
+                author: AgentKey(vec![0_u8; 32]),
+            },
+        })
+        .unwrap();
+        group.throughput(Throughput::Bytes(n as _));
+        group.bench_with_input(BenchmarkId::new("decode", n), &sb, |b, sb| {
+            b.iter(|| decode::<_, Entry>(sb.bytes()).unwrap().header.author);
+        });
+        group.bench_with_input(BenchmarkId::new("field", n), &sb, |b, sb| {
+            b.iter(|| {
+                sb.field::<AgentKey>(&["header", "author"])
+                    .unwrap()
+                    .unwrap()
+            });
+        });
+    }
+
+    group.finish();
+}
+
+pub fn decode_headers(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode");
+
+    for n in [1, 1_000, 100_000] {
+        let bytes = encode(
+            &(0..n)
+                .map(|seq| Header {
+                    seq,
+                    author: AgentKey(vec![0_u8; 32]),
+                })
+                .collect::<Vec<_>>(),
+        )
+        .unwrap();
+        group.throughput(Throughput::Bytes(bytes.len() as _));
+        group.bench_with_input(BenchmarkId::from_parameter(n), &bytes, |b, bytes| {
+            b.iter(|| decode::<_, Vec<Header>>(bytes).unwrap());
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(
+    bench,
+    round_trip_string,
+    round_trip_bytes,
+    round_nested,
+    field_access,
+    decode_headers
+);
 
 criterion_main!(bench);
@@ -1,5 +1,42 @@
 //! at the moment this just tests roughly what it looks like to consume serialized_bytes downstream
 //! which is ideally as little as possible of the underlying abstractions leaked
+//!
+//! `#[derive(SerializedBytes)]` on a type without a trait it needs doesn't compile. Only the
+//! error code is checked here, the full messages are rustc's and change between toolchains.
+//!
+//! without serde
+//! ```compile_fail,E0277
+//! use holochain_serialized_bytes::prelude::*;
+//!
+//! #[derive(Serialize, Debug, SerializedBytes)]
+//! struct Foo {
+//!     inner: String,
+//! }
+//! ```
+//!
+//! without `Debug`
+//! ```compile_fail,E0277
+//! use holochain_serialized_bytes::prelude::*;
+//!
+//! #[derive(Serialize, Deserialize, SerializedBytes)]
+//! struct Foo {
+//!     inner: String,
+//! }
+//! ```
+//!
+//! with an error type that doesn't convert from `SerializedBytesError`
+//! ```compile_fail,E0277
+//! use holochain_serialized_bytes::prelude::*;
+//!
+//! #[derive(Debug)]
+//! struct MyError;
+//!
+//! #[derive(Serialize, Deserialize, Debug, SerializedBytes)]
+//! #[serialized_bytes(error = "MyError")]
+//! struct Foo {
+//!     inner: String,
+//! }
+//! ```
 extern crate holochain_serialized_bytes;
 
 #[cfg(test)]
@@ -27,6 +64,11 @@ pub mod tests {
     // holochain_serial! in prelude
     holochain_serial!(Foo);
 
+    // ? converts the SerializedBytesError to FooError
+    fn foo_from(sb: SerializedBytes) -> Result<Foo, FooError> {
+        Ok(Foo::try_from(sb)?)
+    }
+
     #[test_fuzz::test_fuzz]
     pub fn foo_test(s: String) {
         let foo = Foo { inner: s };
@@ -37,5 +79,9 @@ pub mod tests {
         let other_foo = Foo::try_from(sb).unwrap();
 
         assert_eq!(foo, other_foo);
+
+        // UnsafeBytes in prelude
+        let invalid = SerializedBytes::from(UnsafeBytes::from(vec![0xc1]));
+        assert!(matches!(foo_from(invalid), Err(FooError::Serialize)));
     }
 }
@@ -4,6 +4,33 @@ pub mod tests {
     use serde_json::Value;
 
     use holochain_serialized_bytes::prelude::*;
+    use holochain_serialized_bytes::validate_canonical;
+    use holochain_serialized_bytes::BinFormat;
+    use holochain_serialized_bytes::Blake2b256;
+    use holochain_serialized_bytes::Bytes;
+    use holochain_serialized_bytes::BytesMut;
+    use holochain_serialized_bytes::CanonicalRule;
+    use holochain_serialized_bytes::CanonicalViolation;
+    use holochain_serialized_bytes::ContentHash;
+    use holochain_serialized_bytes::DecodeLimit;
+    use holochain_serialized_bytes::DeserializeError;
+    use holochain_serialized_bytes::DeserializeErrorKind;
+    use holochain_serialized_bytes::FrameError;
+    use holochain_serialized_bytes::FrameStatus;
+    use holochain_serialized_bytes::Framing;
+    use holochain_serialized_bytes::Hashed;
+    use holochain_serialized_bytes::JsonBin;
+    use holochain_serialized_bytes::JsonOptions;
+    use holochain_serialized_bytes::LengthPrefix;
+    use holochain_serialized_bytes::LimitExceeded;
+    use holochain_serialized_bytes::Migrations;
+    use holochain_serialized_bytes::RenderOptions;
+    use holochain_serialized_bytes::SchemaVersion;
+    use holochain_serialized_bytes::SerializedBytesCodec;
+    use holochain_serialized_bytes::SerializedBytesRef;
+    use holochain_serialized_bytes::Sha256;
+    use holochain_serialized_bytes::Signed;
+    use holochain_serialized_bytes::Versioned;
     use std::convert::TryInto;
 
     #[test]
@@ -109,9 +136,13 @@ pub mod tests {
             let decode_error: Result<String, SerializedBytesError> = decode(&bad_bytes);
             assert_eq!(
                 decode_error,
-                Err(SerializedBytesError::Deserialize(
-                    "invalid type: integer `1`, expected a string".into()
-                ))
+                Err(SerializedBytesError::Deserialize(DeserializeError {
+                    kind: DeserializeErrorKind::TypeMismatch,
+                    offset: Some(0),
+                    type_name: "alloc::string::String".into(),
+                    path: "".into(),
+                    message: "invalid type: integer `1`, expected a string".into(),
+                }))
             );
 
             let encode: Result<Vec<u8>, SerializedBytesError> = encode(&());
@@ -241,6 +272,1886 @@ pub mod tests {
         things_that_probably_wont_deserialize(vec![1, 2, 3]);
     }
 
+    #[test]
+    fn canonical_map_order() {
+        use std::collections::HashMap;
+
+        #[derive(Serialize, Debug)]
+        struct Entries {
+            zz: HashMap<String, u32>,
+            a: u32,
+        }
+
+        let keys = ["bb", "a", "ccc", "b", "dd"];
+        let forwards = Entries {
+            zz: keys.iter().map(|k| (k.to_string(), 300)).collect(),
+            a: 1,
+        };
+        let backwards = Entries {
+            zz: keys.iter().rev().map(|k| (k.to_string(), 300)).collect(),
+            a: 1,
+        };
+
+        let canonical = encode_canonical(&forwards).unwrap();
+        assert_eq!(canonical, encode_canonical(&backwards).unwrap());
+        // keys sort by their encoded bytes so shorter fixstr keys always come first
+        assert_eq!(
+            canonical,
+            vec![
+                130, 161, 97, 1, 162, 122, 122, 133, 161, 97, 205, 1, 44, 161, 98, 205, 1, 44, 162,
+                98, 98, 205, 1, 44, 162, 100, 100, 205, 1, 44, 163, 99, 99, 99, 205, 1, 44,
+            ]
+        );
+    }
+
+    #[test]
+    fn canonical_floats() {
+        #[derive(Serialize, Debug)]
+        struct Floats(f32, f64, f64);
+
+        assert_eq!(
+            encode_canonical(&Floats(-0.0, f64::from_bits(0xfff8_0000_0000_0001), 1.5)).unwrap(),
+            vec![
+                147, 202, 0, 0, 0, 0, 203, 127, 248, 0, 0, 0, 0, 0, 0, 203, 63, 248, 0, 0, 0, 0, 0,
+                0
+            ]
+        );
+        assert_eq!(
+            encode_canonical(&Floats(0.0, f64::NAN, 1.5)).unwrap(),
+            encode_canonical(&Floats(-0.0, -f64::NAN, 1.5)).unwrap(),
+        );
+    }
+
+    #[test]
+    fn canonical_matches_encode_for_sorted_input() {
+        // already canonical bytes pass through untouched
+        let foo = fixture_foo();
+        assert_eq!(encode(&foo).unwrap(), encode_canonical(&foo).unwrap());
+    }
+
+    #[test]
+    fn decode_canonical_rejects_malleable_bytes() {
+        fn rule<T: for<'a> Deserialize<'a> + std::fmt::Debug>(
+            bytes: &[u8],
+        ) -> Option<CanonicalViolation> {
+            match decode_canonical::<_, T>(bytes) {
+                Err(SerializedBytesError::NonCanonical(violation)) => Some(violation),
+                _ => None,
+            }
+        }
+
+        let violation = |rule, offset| Some(CanonicalViolation { rule, offset });
+
+        // the canonical bytes themselves are fine
+        assert_eq!(
+            fixture_foo(),
+            decode_canonical(&encode_canonical(&fixture_foo()).unwrap()).unwrap()
+        );
+        assert_eq!(Tiny(5), decode_canonical(&[5]).unwrap());
+
+        // u8 marker for a fixint
+        assert_eq!(
+            violation(CanonicalRule::IntegerMarker, 0),
+            rule::<Tiny>(&[0xcc, 5])
+        );
+        // str32 for a 3 byte string
+        assert_eq!(
+            violation(CanonicalRule::LengthMarker, 7),
+            rule::<Foo>(&[0x81, 0xa5, 105, 110, 110, 101, 114, 0xdb, 0, 0, 0, 3, 102, 111, 111])
+        );
+        // negative zero
+        assert_eq!(
+            violation(CanonicalRule::FloatForm, 0),
+            rule::<f32>(&[0xca, 0x80, 0, 0, 0])
+        );
+
+        #[derive(Deserialize, Debug)]
+        struct Pair {
+            #[allow(dead_code)]
+            a: u8,
+            #[allow(dead_code)]
+            b: u8,
+        }
+        // {"a": 1, "b": 2} is canonical
+        assert!(decode_canonical::<_, Pair>(&[0x82, 0xa1, 97, 1, 0xa1, 98, 2]).is_ok());
+        // {"b": 2, "a": 1} is not
+        assert_eq!(
+            violation(CanonicalRule::MapKeyOrder, 4),
+            rule::<Pair>(&[0x82, 0xa1, 98, 2, 0xa1, 97, 1])
+        );
+        // so encode of a struct with unsorted fields is refused, only encode_canonical round trips
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Unsorted {
+            b: u8,
+            a: u8,
+        }
+        let unsorted = Unsorted { b: 2, a: 1 };
+        assert_eq!(
+            violation(CanonicalRule::MapKeyOrder, 4),
+            rule::<Unsorted>(&encode(&unsorted).unwrap())
+        );
+        assert_eq!(
+            unsorted,
+            decode_canonical(&encode_canonical(&unsorted).unwrap()).unwrap()
+        );
+        // {"a": 1, "a": 2} is not
+        assert_eq!(
+            violation(CanonicalRule::DuplicateMapKey, 4),
+            rule::<Pair>(&[0x82, 0xa1, 97, 1, 0xa1, 97, 2])
+        );
+        // nil followed by junk
+        assert_eq!(
+            violation(CanonicalRule::TrailingBytes, 1),
+            rule::<()>(&[0xc0, 0xde, 0xad])
+        );
+    }
+
+    #[test]
+    fn trailing_bytes() {
+        let bytes = vec![0xc0, 0xde, 0xad];
+
+        assert!(matches!(
+            decode::<_, ()>(&bytes),
+            Err(SerializedBytesError::Deserialize(_))
+        ));
+        assert_eq!(((), 1), decode_prefix(&bytes).unwrap());
+
+        // anything after the value is trailing, even the start of a value that is cut short
+        for trailing in [vec![0xc1], vec![0x91], vec![0xd9], vec![0xc0, 0xc0]] {
+            let mut bytes = encode(&fixture_foo()).unwrap();
+            let len = bytes.len();
+            bytes.extend(&trailing);
+            for limits in [DecodeLimits::UNLIMITED, DecodeLimits::default()] {
+                match decode_with_limits::<_, Foo>(&bytes, &limits) {
+                    Err(SerializedBytesError::Deserialize(err)) => {
+                        assert_eq!(DeserializeErrorKind::TrailingBytes, err.kind);
+                        assert_eq!(Some(len), err.offset);
+                    }
+                    other => panic!("unexpected {:?}", other),
+                }
+            }
+        }
+
+        // read values off the front of a buffer one at a time
+        let mut buffer = encode(&fixture_foo()).unwrap();
+        buffer.extend(encode(&Tiny(5)).unwrap());
+        let (foo, len): (Foo, usize) = decode_prefix(&buffer).unwrap();
+        assert_eq!(fixture_foo(), foo);
+        let (tiny, rest): (Tiny, usize) = decode_prefix(&buffer[len..]).unwrap();
+        assert_eq!(Tiny(5), tiny);
+        assert_eq!(buffer.len(), len + rest);
+
+        // a truncated value is never a prefix
+        assert!(decode_prefix::<_, Foo>(&buffer[..len - 1]).is_err());
+    }
+
+    #[test]
+    fn structured_deserialize_error() {
+        #[derive(Serialize, Deserialize, Debug)]
+        struct Header<A> {
+            seq: u32,
+            author: A,
+        }
+        #[derive(Serialize, Deserialize, Debug)]
+        struct Entry<A> {
+            header: Header<A>,
+        }
+        #[derive(Serialize, Deserialize, Debug)]
+        struct Outer<A> {
+            entry: Entry<A>,
+        }
+
+        let bytes = encode(&Outer {
+            entry: Entry {
+                header: Header { seq: 1, author: 5 },
+            },
+        })
+        .unwrap();
+        let err = match decode::<_, Outer<String>>(&bytes) {
+            Err(SerializedBytesError::Deserialize(err)) => err,
+            other => panic!("unexpected {:?}", other),
+        };
+        assert_eq!(DeserializeErrorKind::TypeMismatch, err.kind);
+        assert_eq!("entry.header.author", err.path);
+        assert!(err.type_name.ends_with("Outer<alloc::string::String>"));
+        // the offset is that of the 5 in place of the author string
+        assert_eq!(Some(bytes.len() - 1), err.offset);
+        assert_eq!(&[5], &bytes[err.offset.unwrap()..]);
+
+        // truncated input points at the value that was cut short
+        let err = match decode::<_, Foo>(&encode(&fixture_foo()).unwrap()[..9]) {
+            Err(SerializedBytesError::Deserialize(err)) => err,
+            other => panic!("unexpected {:?}", other),
+        };
+        assert_eq!(DeserializeErrorKind::UnexpectedEof, err.kind);
+        assert_eq!(Some(7), err.offset);
+
+        // errors still flatten to strings e.g. for the wasm boundary
+        let message: String = SerializedBytesError::Deserialize(err).into();
+        assert!(message.contains("Foo"));
+        assert!(message.contains("byte 7"));
+    }
+
+    #[test]
+    fn deserialize_error_kinds() {
+        #[derive(Serialize, Deserialize, Debug)]
+        struct Named {
+            name: String,
+        }
+        #[derive(Serialize, Deserialize, Debug)]
+        enum Shape {
+            Circle,
+        }
+        /// Rejects everything with a message that looks like a standard serde error.
+        #[derive(Deserialize, Debug)]
+        #[serde(try_from = "String")]
+        struct Picky;
+        impl TryFrom<String> for Picky {
+            type Error = String;
+            fn try_from(_: String) -> Result<Self, String> {
+                Err("missing field in a picky way".into())
+            }
+        }
+
+        fn kind<T: for<'a> Deserialize<'a> + std::fmt::Debug>(
+            bytes: &[u8],
+        ) -> DeserializeErrorKind {
+            match decode::<_, T>(bytes) {
+                Err(SerializedBytesError::Deserialize(err)) => err.kind,
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+
+        let empty_map = [0x80];
+        assert_eq!(
+            DeserializeErrorKind::MissingField,
+            kind::<Named>(&empty_map)
+        );
+        let square = encode(&"Square").unwrap();
+        assert_eq!(DeserializeErrorKind::UnknownVariant, kind::<Shape>(&square));
+        // the kind comes from how the error was raised, not what the message says
+        let name = encode(&"alice").unwrap();
+        assert_eq!(DeserializeErrorKind::Custom, kind::<Picky>(&name));
+
+        // errors written when the payload was only a message still read
+        let old = [
+            0x81, 0xab, b'D', b'e', b's', b'e', b'r', b'i', b'a', b'l', b'i', b'z', b'e', 0xa3,
+            b'b', b'a', b'd',
+        ];
+        match decode::<_, SerializedBytesError>(&old).unwrap() {
+            SerializedBytesError::Deserialize(err) => {
+                assert_eq!(DeserializeError::from("bad"), err);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        // and the current form round trips
+        let err = SerializedBytesError::Deserialize(match decode::<_, Named>(&empty_map) {
+            Err(SerializedBytesError::Deserialize(err)) => err,
+            other => panic!("unexpected {:?}", other),
+        });
+        assert_eq!(err, decode(&encode(&err).unwrap()).unwrap());
+    }
+
+    #[test]
+    fn decode_limits() {
+        fn exceeded<T: for<'a> Deserialize<'a> + std::fmt::Debug>(
+            bytes: &[u8],
+            limits: &DecodeLimits,
+        ) -> LimitExceeded {
+            let exceeded = match decode_with_limits::<_, T>(bytes, limits) {
+                Err(SerializedBytesError::LimitExceeded(exceeded)) => exceeded,
+                other => panic!("unexpected {:?}", other),
+            };
+            // streams are checked as they are read, to the same effect
+            match decode_from_with_limits::<T, _>(bytes, limits) {
+                Err(SerializedBytesError::LimitExceeded(from_reader)) => {
+                    assert_eq!(exceeded, from_reader)
+                }
+                other => panic!("unexpected {:?}", other),
+            }
+            exceeded
+        }
+
+        // an array32 declaring u32::MAX items is an error rather than an allocation
+        let huge = [0xdd, 0xff, 0xff, 0xff, 0xff];
+        assert!(decode_with_limits::<_, Vec<u8>>(&huge, &DecodeLimits::default()).is_err());
+        assert!(decode::<_, Vec<u8>>(&huge).is_err());
+        // a stream can't be checked for what it doesn't hold so the header alone is refused
+        let huge_str = std::io::Cursor::new([0xdb, 0xff, 0xff, 0xff, 0xff, b'a']);
+        assert_eq!(
+            Err(SerializedBytesError::LimitExceeded(LimitExceeded {
+                limit: DecodeLimit::StringLen,
+                max: DecodeLimits::default().max_string_len,
+                offset: 0,
+            })),
+            decode_from_with_limits::<String, _>(huge_str, &DecodeLimits::default()),
+        );
+
+        // deep nesting never reaches the recursive deserializer
+        let deep = [vec![0x91; 100_000], vec![0xc0]].concat();
+        assert_eq!(
+            LimitExceeded {
+                limit: DecodeLimit::Depth,
+                max: 128,
+                offset: 128
+            },
+            exceeded::<Value>(&deep, &DecodeLimits::default()),
+        );
+
+        let limits = DecodeLimits {
+            max_depth: 2,
+            max_container_len: 3,
+            max_string_len: 4,
+            max_alloc_bytes: 64,
+        };
+        assert_eq!(
+            vec![vec![1_u8]],
+            decode_with_limits::<_, Vec<Vec<u8>>>(&encode(&vec![vec![1_u8]]).unwrap(), &limits)
+                .unwrap()
+        );
+        assert_eq!(
+            DecodeLimit::Depth,
+            exceeded::<Value>(&encode(&vec![vec![vec![1_u8]]]).unwrap(), &limits).limit,
+        );
+        assert_eq!(
+            DecodeLimit::ContainerLen,
+            exceeded::<Vec<u8>>(&encode(&vec![1_u8; 4]).unwrap(), &limits).limit,
+        );
+        assert_eq!(
+            LimitExceeded {
+                limit: DecodeLimit::StringLen,
+                max: 4,
+                offset: 1
+            },
+            exceeded::<Vec<String>>(&encode(&vec!["hello"]).unwrap(), &limits),
+        );
+        assert_eq!(
+            DecodeLimit::AllocBytes,
+            exceeded::<Vec<Vec<u8>>>(&encode(&vec![vec![1_u8; 3]; 3]).unwrap(), &limits).limit,
+        );
+
+        // trailing bytes are still rejected
+        assert!(matches!(
+            decode_with_limits::<_, ()>(&[0xc0, 0xc0], &limits),
+            Err(SerializedBytesError::Deserialize(_))
+        ));
+    }
+
+    #[test]
+    fn serialized_bytes_ref() {
+        #[derive(Serialize, Deserialize, Debug)]
+        struct Borrowed<'a> {
+            name: &'a str,
+            #[serde(with = "serde_bytes")]
+            data: &'a [u8],
+        }
+
+        let sb = SerializedBytes::try_from(fixture_foo()).unwrap();
+        let sb_ref = SerializedBytesRef::from(&sb);
+        assert_eq!(fixture_foo(), Foo::try_from(sb_ref).unwrap());
+        assert_eq!(format!("{:?}", sb), format!("{:?}", sb_ref));
+        assert_eq!(sb, SerializedBytes::from(sb_ref));
+
+        // e.g. a frame read off the network with a header in front of the value
+        let mut frame = vec![0xff, 0xff];
+        frame.extend(
+            encode(&Borrowed {
+                name: "alice",
+                data: &[1, 2, 3],
+            })
+            .unwrap(),
+        );
+        let sb_ref = SerializedBytesRef::from(&frame[2..]);
+        let borrowed: Borrowed<'_> = sb_ref.decode().unwrap();
+        assert_eq!("alice", borrowed.name);
+        assert_eq!(&[1, 2, 3], borrowed.data);
+        // both fields point into the frame rather than at copies of it
+        let frame_range = frame.as_ptr_range();
+        assert!(frame_range.contains(&borrowed.name.as_ptr()));
+        assert!(frame_range.contains(&borrowed.data.as_ptr()));
+
+        assert!(Foo::try_from(SerializedBytesRef::from(&frame[..])).is_err());
+    }
+
+    #[test]
+    fn shared_bytes() {
+        let sb = SerializedBytes::try_from(fixture_bar()).unwrap();
+        let fanned_out: Vec<SerializedBytes> = (0..10).map(|_| sb.clone()).collect();
+        // every clone shares the same bytes
+        for clone in fanned_out.iter() {
+            assert_eq!(sb.bytes().as_ptr(), clone.bytes().as_ptr());
+            assert_eq!(fixture_bar(), Bar::try_from(clone.to_owned()).unwrap());
+        }
+        let copied = SerializedBytes::try_from(&sb).unwrap();
+        assert_eq!(sb.bytes().as_ptr(), copied.bytes().as_ptr());
+
+        // UnsafeBytes round trips don't copy either
+        let v = encode(&fixture_foo()).unwrap();
+        let ptr = v.as_ptr();
+        let sb = SerializedBytes::from(UnsafeBytes::from(v));
+        assert_eq!(ptr, sb.bytes().as_ptr());
+        let v: Vec<u8> = UnsafeBytes::from(sb).into();
+        assert_eq!(ptr, v.as_ptr());
+
+        // a value sliced out of a larger buffer still points into the buffer
+        let mut buffer = vec![0xff];
+        buffer.extend(encode(&fixture_foo()).unwrap());
+        let buffer = Bytes::from(buffer);
+        let sb = SerializedBytes::from(UnsafeBytes::from(buffer.slice(1..)));
+        assert_eq!(buffer[1..].as_ptr(), sb.bytes().as_ptr());
+        assert_eq!(fixture_foo(), Foo::try_from(sb).unwrap());
+
+        // and so does slicing SerializedBytes
+        let foo = encode(&fixture_foo()).unwrap();
+        let two = SerializedBytes::from(UnsafeBytes::from([foo.clone(), foo.clone()].concat()));
+        let second = two.slice(foo.len()..);
+        assert_eq!(two.bytes()[foo.len()..].as_ptr(), second.bytes().as_ptr());
+        assert_eq!(fixture_foo(), Foo::try_from(second).unwrap());
+        assert_eq!(
+            two.bytes().as_ptr(),
+            two.slice(..foo.len()).bytes().as_ptr()
+        );
+    }
+
+    #[test]
+    fn streaming() {
+        // the same bytes as encode, appended to whatever is already there
+        let mut buf = Vec::with_capacity(256);
+        buf.push(0xff);
+        encode_into_vec(&fixture_foo(), &mut buf).unwrap();
+        encode_into_vec(&fixture_bar(), &mut buf).unwrap();
+        let mut expected = vec![0xff];
+        expected.extend(encode(&fixture_foo()).unwrap());
+        expected.extend(encode(&fixture_bar()).unwrap());
+        assert_eq!(expected, buf);
+
+        // a failed encode leaves the buffer alone, even if it had written part of the value
+        #[derive(Debug)]
+        struct BadSerialize;
+        impl serde::Serialize for BadSerialize {
+            fn serialize<S: serde::Serializer>(&self, _: S) -> Result<S::Ok, S::Error> {
+                Err(serde::ser::Error::custom("Cannot serialize!"))
+            }
+        }
+        let mut failed = buf.clone();
+        assert!(encode_into_vec(&(1_u8, BadSerialize), &mut failed).is_err());
+        assert_eq!(buf, failed);
+
+        let mut written = Vec::new();
+        encode_into(&fixture_foo(), &mut written).unwrap();
+        assert_eq!(encode(&fixture_foo()).unwrap(), written);
+
+        // read values off a stream one at a time
+        let mut reader = std::io::Cursor::new(&buf[1..]);
+        assert_eq!(fixture_foo(), decode_from::<Foo, _>(&mut reader).unwrap());
+        assert_eq!(fixture_bar(), decode_from::<Bar, _>(&mut reader).unwrap());
+        assert_eq!(buf.len() - 1, reader.position() as usize);
+        assert!(decode_from::<Foo, _>(&mut reader).is_err());
+
+        // errors are as detailed as decode, other than the offset being as far as the reader got
+        let bytes = encode(&fixture_bar()).unwrap();
+        match (decode::<_, Foo>(&bytes), decode_from::<Foo, _>(&bytes[..])) {
+            (
+                Err(SerializedBytesError::Deserialize(expected)),
+                Err(SerializedBytesError::Deserialize(err)),
+            ) => {
+                assert_eq!(
+                    DeserializeError {
+                        offset: None,
+                        ..expected
+                    },
+                    DeserializeError {
+                        offset: None,
+                        ..err
+                    }
+                );
+                assert!(err.offset.unwrap() <= bytes.len());
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        // values within the limits read exactly as without them
+        let mut reader = std::io::Cursor::new(&buf[1..]);
+        let limits = DecodeLimits::default();
+        assert_eq!(
+            fixture_foo(),
+            decode_from_with_limits::<Foo, _>(&mut reader, &limits).unwrap()
+        );
+        assert_eq!(
+            fixture_bar(),
+            decode_from_with_limits::<Bar, _>(&mut reader, &limits).unwrap()
+        );
+        assert_eq!(buf.len() - 1, reader.position() as usize);
+    }
+
+    #[test]
+    fn encoded_len_matches_encode() {
+        fn check<T: Serialize + std::fmt::Debug>(val: T) {
+            let bytes = encode(&val).unwrap();
+            assert_eq!(bytes.len(), encoded_len(&val).unwrap());
+        }
+        check(());
+        check(fixture_foo());
+        check(fixture_bar());
+        check(Baz {
+            wow: Some(BazResult::Ok(vec![0_u8; 70_000])),
+        });
+        check(IncludesSerializedBytes {
+            inner: SerializedBytes::try_from(fixture_foo()).unwrap(),
+        });
+        check(vec!["a".repeat(300); 20]);
+        check((u64::MAX, i64::MIN, 1.5_f32, 1.5_f64));
+    }
+
+    #[test]
+    fn framing() {
+        let foo = SerializedBytes::try_from(fixture_foo()).unwrap();
+        let big = SerializedBytes::try_from(SomeBytes(vec![7; 300])).unwrap();
+
+        for prefix in [LengthPrefix::U32, LengthPrefix::Varint] {
+            let framing = Framing {
+                prefix,
+                ..Default::default()
+            };
+            let mut stream = Vec::new();
+            framing.encode_frame(&foo, &mut stream).unwrap();
+            framing.write_frame(&big, &mut stream).unwrap();
+
+            // blocking reads
+            let mut reader = &stream[..];
+            assert_eq!(Some(foo.clone()), framing.read_frame(&mut reader).unwrap());
+            assert_eq!(Some(big.clone()), framing.read_frame(&mut reader).unwrap());
+            assert_eq!(None, framing.read_frame(&mut reader).unwrap());
+
+            // a stream ending mid frame is truncated rather than the end of the stream
+            let first_frame_len = match framing
+                .decode_frame(&mut BytesMut::from(&stream[..]))
+                .unwrap()
+            {
+                FrameStatus::Complete { len, .. } => len,
+                other => panic!("unexpected {:?}", other),
+            };
+            for cut in 1..stream.len() {
+                let mut reader = &stream[..cut];
+                let frames = std::iter::from_fn(|| framing.read_frame(&mut reader).transpose())
+                    .collect::<Result<Vec<_>, _>>();
+                if cut == first_frame_len {
+                    assert_eq!(vec![foo.clone()], frames.unwrap());
+                } else {
+                    assert!(matches!(
+                        frames,
+                        Err(SerializedBytesError::Frame(FrameError::Truncated { .. }))
+                    ));
+                }
+            }
+
+            // non-blocking reads as bytes trickle in
+            let mut received = BytesMut::new();
+            let mut frames = Vec::new();
+            for byte in stream.iter() {
+                received.extend_from_slice(&[*byte]);
+                match framing.decode_frame(&mut received).unwrap() {
+                    FrameStatus::Complete { frame, .. } => frames.push(frame),
+                    FrameStatus::Incomplete { needed } => assert!(needed > 0),
+                }
+            }
+            assert_eq!(vec![foo.clone(), big.clone()], frames);
+            assert!(received.is_empty());
+
+            // the frame bytes decode as usual
+            assert_eq!(fixture_foo(), Foo::try_from(frames.remove(0)).unwrap());
+        }
+
+        // varint prefixes are as short as possible, 7 bits per byte
+        assert_eq!(303, big.bytes().len());
+        let framing = Framing {
+            prefix: LengthPrefix::Varint,
+            ..Default::default()
+        };
+        let mut stream = Vec::new();
+        framing.encode_frame(&big, &mut stream).unwrap();
+        assert_eq!(&[0xaf, 0x02], &stream[..2]);
+        assert_eq!(
+            FrameStatus::Incomplete {
+                needed: big.bytes().len() - 1
+            },
+            framing
+                .decode_frame(&mut BytesMut::from(&stream[..3]))
+                .unwrap()
+        );
+        // and never longer, so every frame has exactly one encoding
+        for prefix in [&[0x80, 0x00][..], &[0x81, 0x80, 0x00], &[0x80; 9]] {
+            assert_eq!(
+                Err(SerializedBytesError::Frame(FrameError::InvalidPrefix)),
+                framing.decode_frame(&mut BytesMut::from([prefix, &[0x00]].concat().as_slice())),
+            );
+        }
+
+        // the frame is split off the buffer rather than copied
+        let mut buf = BytesMut::from(&stream[..]);
+        let start = buf.as_ptr();
+        match framing.decode_frame(&mut buf).unwrap() {
+            FrameStatus::Complete { frame, len } => {
+                assert_eq!(big, frame);
+                assert_eq!(start.wrapping_add(2), frame.bytes().as_ptr());
+                assert_eq!(stream.len() - len, buf.len());
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        // max frame size on both sides, checked before the frame arrives
+        let small = Framing {
+            prefix: LengthPrefix::U32,
+            max_frame_size: 100,
+        };
+        assert_eq!(
+            Err(SerializedBytesError::Frame(FrameError::TooLarge {
+                len: big.bytes().len() as u64,
+                max: 100
+            })),
+            small.encode_frame(&big, &mut Vec::new())
+        );
+        assert!(matches!(
+            small.decode_frame(&mut BytesMut::from(&[0xff, 0xff, 0xff, 0xff][..])),
+            Err(SerializedBytesError::Frame(FrameError::TooLarge { .. }))
+        ));
+        assert!(matches!(
+            framing.decode_frame(&mut BytesMut::from(&[0xff; 11][..])),
+            Err(SerializedBytesError::Frame(FrameError::InvalidPrefix))
+        ));
+    }
+
+    #[tokio::test]
+    async fn codec_round_trip() {
+        use futures::{SinkExt, StreamExt};
+        use tokio_util::codec::{FramedRead, FramedWrite};
+
+        // the codec errors are io errors holding the SerializedBytesError
+        fn inner(err: std::io::Error) -> SerializedBytesError {
+            *err.into_inner().unwrap().downcast().unwrap()
+        }
+
+        let bars = vec![
+            fixture_bar(),
+            Bar { whatever: vec![] },
+            Bar {
+                whatever: vec![9; 100_000],
+            },
+        ];
+
+        // a small pipe so frames are split across many reads and writes
+        let (client, server) = tokio::io::duplex(64);
+        let mut sink = FramedWrite::new(client, SerializedBytesCodec::<Bar>::default());
+        let mut stream = FramedRead::new(server, SerializedBytesCodec::<Bar>::default());
+
+        let sent = bars.clone();
+        let send = async move {
+            for bar in sent {
+                sink.send(bar).await.unwrap();
+            }
+        };
+        let receive = async move {
+            let mut received = Vec::new();
+            while let Some(bar) = stream.next().await {
+                received.push(bar.unwrap());
+            }
+            received
+        };
+        let ((), received) = futures::join!(send, receive);
+        assert_eq!(bars, received);
+
+        // the max frame size applies to both sides
+        let framing = Framing {
+            max_frame_size: 100,
+            ..Default::default()
+        };
+        let mut sink = FramedWrite::new(Vec::new(), SerializedBytesCodec::<Bar>::new(framing));
+        assert!(matches!(
+            sink.send(bars[2].clone()).await.map_err(inner),
+            Err(SerializedBytesError::Frame(FrameError::TooLarge { .. }))
+        ));
+        // the declared length is refused before the rest of the frame arrives
+        let mut stream = FramedRead::new(
+            &[0, 0, 0, 101][..],
+            SerializedBytesCodec::<Bar>::new(framing),
+        );
+        assert!(matches!(
+            stream.next().await.unwrap().map_err(inner),
+            Err(SerializedBytesError::Frame(FrameError::TooLarge {
+                len: 101,
+                max: 100
+            }))
+        ));
+
+        // a stream that ends mid frame is truncated, and undecodable frames are errors
+        let mut stream = FramedRead::new(
+            &[0, 0, 0, 3, 0xc0][..],
+            SerializedBytesCodec::<Bar>::default(),
+        );
+        assert!(matches!(
+            stream.next().await.unwrap().map_err(inner),
+            Err(SerializedBytesError::Frame(FrameError::Truncated {
+                expected: 7,
+                got: 5
+            }))
+        ));
+        let mut stream = FramedRead::new(
+            &[0, 0, 0, 1, 0xc0][..],
+            SerializedBytesCodec::<Bar>::default(),
+        );
+        assert!(matches!(
+            stream.next().await.unwrap().map_err(inner),
+            Err(SerializedBytesError::Deserialize(_))
+        ));
+
+        // types with their own error type can use it for the codec errors too
+        let (client, server) = tokio::io::duplex(64);
+        let mut sink = FramedWrite::new(
+            client,
+            SerializedBytesCodec::<ZomeOutput, ZomeError>::default(),
+        );
+        let mut stream = FramedRead::new(
+            server,
+            SerializedBytesCodec::<ZomeOutput, ZomeError>::default(),
+        );
+        sink.send(ZomeOutput(5)).await.unwrap();
+        assert_eq!(Some(Ok(ZomeOutput(5))), stream.next().await);
+        let mut stream = FramedRead::new(
+            &[0, 0, 0, 1, 0xc0][..],
+            SerializedBytesCodec::<ZomeInput, ZomeError>::default(),
+        );
+        assert!(matches!(
+            stream.next().await,
+            Some(Err(ZomeError::Serialization(
+                SerializedBytesError::Deserialize(_)
+            )))
+        ));
+    }
+
+    #[test]
+    fn content_hashing() {
+        let sb = SerializedBytes::try_from(fixture_foo()).unwrap();
+        // {"inner": "foo"}
+        assert_eq!(
+            [
+                0xe2, 0xf4, 0x54, 0xe0, 0x37, 0x09, 0x07, 0x88, 0x59, 0x93, 0x25, 0xa2, 0xbe, 0xda,
+                0x42, 0x78, 0x7b, 0xb9, 0xa0, 0x78, 0xa5, 0x1a, 0xa6, 0xe1, 0xd5, 0x17, 0xf9, 0x76,
+                0xba, 0xdb, 0x22, 0xe7
+            ],
+            sb.sha256()
+        );
+        assert_eq!(
+            [
+                0x19, 0x2e, 0x41, 0xd3, 0x39, 0xca, 0x29, 0xbb, 0x4f, 0x8e, 0x7a, 0x82, 0x4e, 0x42,
+                0x9f, 0x18, 0x5e, 0x0b, 0x6f, 0xd6, 0x2b, 0xd8, 0x7e, 0x69, 0xf5, 0x98, 0x45, 0x21,
+                0x5b, 0x9b, 0x49, 0x08
+            ],
+            sb.hash_blake2b_256()
+        );
+
+        // every way of hashing foo hashes the same bytes
+        assert_eq!(sb.sha256(), fixture_foo().content_hash::<Sha256>().unwrap());
+        assert_eq!(sb.sha256(), sb.content_hash::<Sha256>().unwrap());
+        assert_eq!(
+            sb.hash_blake2b_256(),
+            fixture_foo().content_hash::<Blake2b256>().unwrap()
+        );
+        assert_ne!(sb.sha256(), sb.hash_blake2b_256());
+
+        let hashed: Hashed<Foo> = Hashed::new(fixture_foo()).unwrap();
+        assert_eq!(&sb.hash_blake2b_256(), hashed.hash());
+        assert_eq!("foo", hashed.inner);
+        let hashed: Hashed<Foo, Sha256> = Hashed::new(fixture_foo()).unwrap();
+        assert_eq!((fixture_foo(), sb.sha256()), hashed.into_parts());
+    }
+
+    #[test]
+    fn signed() {
+        let signing_key = ed25519_dalek::SigningKey::from_bytes(&[7; 32]);
+        let verifying_key = signing_key.verifying_key();
+        let other_key = ed25519_dalek::SigningKey::from_bytes(&[8; 32]).verifying_key();
+
+        let signed = Signed::sign(&fixture_foo(), &signing_key).unwrap();
+        assert_eq!(fixture_foo(), signed.verify(&verifying_key).unwrap());
+        assert!(matches!(
+            signed.verify(&other_key),
+            Err(SerializedBytesError::Signature(_))
+        ));
+
+        // signed values go over the wire like anything else
+        let sb = SerializedBytes::try_from(&signed).unwrap();
+        let received = Signed::<Foo>::try_from(sb).unwrap();
+        assert_eq!(fixture_foo(), received.verify(&verifying_key).unwrap());
+
+        // changing a single byte of the signed bytes breaks the signature, even if the bytes
+        // still decode to the same value, e.g. an overlong str length marker
+        let (bytes, signature) = received.into_parts();
+        let mut tampered = bytes.bytes().to_vec();
+        assert_eq!(0xa3, tampered[7]);
+        tampered.splice(7..8, [0xd9, 3]);
+        assert_eq!(fixture_foo(), decode(&tampered).unwrap());
+        let tampered = Signed::<Foo>::from_parts(
+            SerializedBytes::from(UnsafeBytes::from(tampered)),
+            signature.clone(),
+        );
+        assert!(matches!(
+            tampered.verify(&verifying_key),
+            Err(SerializedBytesError::Signature(_))
+        ));
+        assert!(Signed::<Foo>::from_parts(bytes, signature[1..].to_vec())
+            .verify(&verifying_key)
+            .is_err());
+
+        // the signed bytes are the serialized bytes as they are, so they hash the same
+        let signed = Signed::sign(&fixture_foo(), &signing_key).unwrap();
+        assert_eq!(
+            &SerializedBytes::try_from(fixture_foo()).unwrap(),
+            signed.bytes()
+        );
+        assert_eq!(
+            fixture_foo().content_hash::<Blake2b256>().unwrap(),
+            signed.bytes().hash_blake2b_256()
+        );
+
+        // including types encoded with something other than messagepack
+        let signed = Signed::sign(&Checksum(0xdeadbeef), &signing_key).unwrap();
+        assert_eq!(&[0xde, 0xad, 0xbe, 0xef], signed.bytes().bytes());
+        assert_eq!(Checksum(0xdeadbeef), signed.verify(&verifying_key).unwrap());
+    }
+
+    #[test]
+    fn versioned() {
+        mod v1 {
+            use holochain_serialized_bytes::prelude::*;
+            use holochain_serialized_bytes::SchemaVersion;
+
+            #[derive(Serialize, Deserialize, Debug, PartialEq, SerializedBytes)]
+            pub struct Entry {
+                pub name: String,
+            }
+
+            impl SchemaVersion for Entry {
+                const SCHEMA_VERSION: u32 = 1;
+            }
+        }
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq, SerializedBytes)]
+        struct Entry {
+            name: String,
+            tags: Vec<String>,
+        }
+
+        impl SchemaVersion for Entry {
+            const SCHEMA_VERSION: u32 = 2;
+        }
+
+        impl From<v1::Entry> for Entry {
+            fn from(v1: v1::Entry) -> Self {
+                Entry {
+                    name: v1.name,
+                    tags: vec![],
+                }
+            }
+        }
+
+        let migrations = Migrations::<Entry>::new()
+            .register(1, |content| v1::Entry::try_from(content).map(Entry::from));
+
+        // stored by an older version
+        let stored =
+            SerializedBytes::try_from(Versioned::new(&v1::Entry { name: "old".into() }).unwrap())
+                .unwrap();
+        assert_eq!(
+            r#"{"version":1,"content":[129,164,110,97,109,101,163,111,108,100]}"#,
+            format!("{:?}", stored)
+        );
+        // the new type alone can't make sense of the old bytes
+        let versioned = Versioned::<Entry>::try_from(stored).unwrap();
+        assert_eq!(1, versioned.version());
+        assert!(Entry::try_from(versioned.content().clone()).is_err());
+        assert_eq!(
+            Entry {
+                name: "old".into(),
+                tags: vec![]
+            },
+            versioned.decode(&migrations).unwrap()
+        );
+
+        // the current version needs no migration
+        let current = Entry {
+            name: "new".into(),
+            tags: vec!["a".into()],
+        };
+        let stored = SerializedBytes::try_from(Versioned::new(&current).unwrap()).unwrap();
+        assert_eq!(
+            current,
+            Versioned::<Entry>::try_from(stored)
+                .unwrap()
+                .decode(&Migrations::new())
+                .unwrap()
+        );
+
+        // versions without a migration, including newer versions, are an error
+        for version in [0, 3] {
+            let err = Versioned::<Entry>::from_parts(version, SerializedBytes::default())
+                .decode(&migrations)
+                .unwrap_err();
+            match err {
+                SerializedBytesError::Deserialize(err) => {
+                    assert_eq!(DeserializeErrorKind::UnsupportedVersion, err.kind)
+                }
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn legacy_enums() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        #[serde(rename_all = "snake_case", tag = "type", content = "data")]
+        enum ConductorApi {
+            Request { param: i32 },
+        }
+
+        // {"type": {"request": null}, "data": {"param": 100}} from the 0.0.54 CHANGELOG entry
+        let legacy = [
+            130, 164, 116, 121, 112, 101, 129, 167, 114, 101, 113, 117, 101, 115, 116, 192, 164,
+            100, 97, 116, 97, 129, 165, 112, 97, 114, 97, 109, 100,
+        ];
+        // {"type": "request", "data": {"param": 100}} from the conductor_api test
+        let current = [
+            130, 164, 116, 121, 112, 101, 167, 114, 101, 113, 117, 101, 115, 116, 164, 100, 97,
+            116, 97, 129, 165, 112, 97, 114, 97, 109, 100,
+        ];
+        for bytes in [&legacy[..], &current[..]] {
+            assert_eq!(
+                ConductorApi::Request { param: 100 },
+                decode_legacy(bytes).unwrap()
+            );
+        }
+
+        // the same request internally tagged, where only decode_legacy understands the legacy tag
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        #[serde(rename_all = "snake_case", tag = "type")]
+        enum Internal {
+            Request { param: i32 },
+            Ping,
+        }
+        // {"type": {"request": null}, "param": 100}
+        let legacy = [
+            130, 164, 116, 121, 112, 101, 129, 167, 114, 101, 113, 117, 101, 115, 116, 192, 165,
+            112, 97, 114, 97, 109, 100,
+        ];
+        assert!(decode::<_, Internal>(&legacy).is_err());
+        assert_eq!(
+            Internal::Request { param: 100 },
+            decode_legacy(&legacy).unwrap()
+        );
+        let current = encode(&Internal::Request { param: 100 }).unwrap();
+        assert_eq!(
+            Internal::Request { param: 100 },
+            decode_legacy(&current).unwrap()
+        );
+
+        // any number of legacy variants, mixed in with current ones
+        let mut mixed = vec![0x93];
+        mixed.extend(encode(&Internal::Ping).unwrap());
+        mixed.extend([
+            0x81, 0xa4, b't', b'y', b'p', b'e', 0x81, 0xa4, b'p', b'i', b'n', b'g', 0xc0,
+        ]);
+        mixed.extend(&legacy);
+        assert_eq!(
+            vec![
+                Internal::Ping,
+                Internal::Ping,
+                Internal::Request { param: 100 }
+            ],
+            decode_legacy::<_, Vec<Internal>>(&mixed).unwrap()
+        );
+
+        // errors after legacy tags still point at the bad value
+        let mut bad = mixed.clone();
+        bad.extend(encode(&"not an Internal").unwrap());
+        bad[0] = 0x94;
+        let err = match decode_legacy::<_, Vec<Internal>>(&bad) {
+            Err(SerializedBytesError::Deserialize(err)) => err,
+            other => panic!("unexpected {:?}", other),
+        };
+        assert_eq!("[3]", err.path);
+        assert_eq!(Some(mixed.len()), err.offset);
+
+        let mut trailing = mixed.clone();
+        trailing.push(0xc0);
+        match decode_legacy::<_, Vec<Internal>>(&trailing) {
+            Err(SerializedBytesError::Deserialize(err)) => {
+                assert_eq!(DeserializeErrorKind::TrailingBytes, err.kind);
+                assert_eq!(Some(mixed.len()), err.offset);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        // only enum tags take the legacy form, anywhere else the map is still a map
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Named {
+            name: String,
+        }
+        // {"name": {"alice": nil}}
+        let named = [
+            0x81, 0xa4, b'n', b'a', b'm', b'e', 0x81, 0xa5, b'a', b'l', b'i', b'c', b'e', 0xc0,
+        ];
+        match decode_legacy::<_, Named>(&named) {
+            Err(SerializedBytesError::Deserialize(err)) => {
+                assert_eq!(DeserializeErrorKind::TypeMismatch, err.kind);
+                assert_eq!("name", err.path);
+                assert_eq!(Some(6), err.offset);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn generic_derive() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug, Clone, SerializedBytes)]
+        struct Wrapper<T>(T);
+
+        #[derive(Serialize, Deserialize, PartialEq, Debug, Clone, SerializedBytes)]
+        struct Pair<A, B = String>
+        where
+            A: Clone,
+        {
+            a: A,
+            b: B,
+        }
+
+        #[derive(Serialize, Deserialize, PartialEq, Debug, Clone, SerializedBytes)]
+        struct Fixed<const N: usize> {
+            #[serde(with = "serde_bytes")]
+            bytes: [u8; N],
+        }
+
+        #[derive(Serialize, Deserialize, PartialEq, Debug, Clone, SerializedBytes)]
+        enum Either<L, R> {
+            Left(L),
+            Right(R),
+        }
+
+        #[derive(Serialize, Deserialize, PartialEq, Debug, Clone, SerializedBytes)]
+        struct Named<'a> {
+            name: std::borrow::Cow<'a, str>,
+        }
+
+        #[derive(Serialize, Deserialize, PartialEq, Debug, SerializedBytes)]
+        struct Borrowed<'a, T> {
+            name: &'a str,
+            inner: T,
+        }
+
+        fn round_trip<T>(t: T)
+        where
+            T: PartialEq + std::fmt::Debug + Clone,
+            SerializedBytes: TryFrom<T, Error = SerializedBytesError>,
+            T: TryFrom<SerializedBytes, Error = SerializedBytesError>,
+        {
+            let sb = SerializedBytes::try_from(t.clone()).unwrap();
+            assert_eq!(t, T::try_from(sb).unwrap());
+        }
+
+        round_trip(Wrapper(fixture_foo()));
+        round_trip(Wrapper(Wrapper(5_u8)));
+        round_trip(Pair {
+            a: 1_u32,
+            b: "b".to_string(),
+        });
+        round_trip(Pair { a: (), b: vec![1] });
+        round_trip(Fixed { bytes: [1, 2, 3] });
+        round_trip(Fixed { bytes: [0; 32] });
+        round_trip(Either::<u8, String>::Left(1));
+        round_trip(Either::<u8, String>::Right("r".into()));
+        round_trip(Named {
+            name: "owned".into(),
+        });
+
+        // generic types serialize exactly as serde says, like any other type
+        assert_eq!(
+            encode(&fixture_foo()).unwrap(),
+            SerializedBytes::try_from(Wrapper(fixture_foo()))
+                .unwrap()
+                .bytes()
+        );
+
+        // borrowing types can be encoded, and decoded from a SerializedBytesRef they borrow from
+        let sb = SerializedBytes::try_from(Borrowed {
+            name: "alice",
+            inner: Wrapper(1_u8),
+        })
+        .unwrap();
+        let borrowed = Borrowed::<Wrapper<u8>>::try_from(SerializedBytesRef::from(&sb)).unwrap();
+        assert_eq!("alice", borrowed.name);
+        assert_eq!(Wrapper(1), borrowed.inner);
+    }
+
+    #[test]
+    fn compact_encoding() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug, Clone, SerializedBytes)]
+        #[serialized_bytes(compact)]
+        struct Point {
+            x: u8,
+            y: String,
+        }
+
+        #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+        struct Line {
+            from: Point,
+            to: Point,
+        }
+        holochain_serial!(compact Line);
+
+        let point = Point {
+            x: 1,
+            y: "a".into(),
+        };
+        let sb = SerializedBytes::try_from(point.clone()).unwrap();
+        assert_eq!(&[0x92, 0x01, 0xa1, b'a'], sb.bytes());
+        assert_eq!(encode_compact(&point).unwrap(), sb.bytes());
+        assert_eq!(point, Point::try_from(sb).unwrap());
+
+        // only the fields of the compact type itself are an array, the structs inside it are
+        // written as `encode` writes them, whether or not they are compact types themselves
+        let line = Line {
+            from: point.clone(),
+            to: point.clone(),
+        };
+        let sb = SerializedBytes::try_from(line.clone()).unwrap();
+        let point_map = [0x82, 0xa1, b'x', 0x01, 0xa1, b'y', 0xa1, b'a'];
+        assert_eq!(&[&[0x92][..], &point_map, &point_map].concat(), sb.bytes());
+        assert_eq!(line, Line::try_from(sb).unwrap());
+
+        // a compact type inside a type that isn't compact is a map like everything else
+        #[derive(Serialize, Deserialize, PartialEq, Debug, Clone, SerializedBytes)]
+        struct Labelled {
+            point: Point,
+        }
+        let labelled = Labelled {
+            point: point.clone(),
+        };
+        let sb = SerializedBytes::try_from(labelled.clone()).unwrap();
+        assert_eq!(
+            &[&[0x81, 0xa5, b'p', b'o', b'i', b'n', b't'][..], &point_map].concat(),
+            sb.bytes()
+        );
+        assert_eq!(labelled, Labelled::try_from(sb).unwrap());
+
+        // struct variants of a compact enum keep their variant name
+        #[derive(Serialize, Deserialize, PartialEq, Debug, Clone, SerializedBytes)]
+        #[serialized_bytes(compact)]
+        enum Shape {
+            Dot { at: Point },
+        }
+        let shape = Shape::Dot { at: point.clone() };
+        let sb = SerializedBytes::try_from(shape.clone()).unwrap();
+        assert_eq!(
+            &[&[0x81, 0xa3, b'D', b'o', b't', 0x91][..], &point_map].concat(),
+            sb.bytes()
+        );
+        assert_eq!(shape, Shape::try_from(sb).unwrap());
+
+        // the compact form is still canonical
+        validate_canonical(&encode_compact(&line).unwrap()).unwrap();
+
+        // decoding accepts either form, other types are unaffected
+        assert_eq!(point, decode::<_, Point>(&encode(&point).unwrap()).unwrap());
+        assert_eq!(&point_map, &encode(&point).unwrap()[..]);
+        assert_eq!(encode(&7_u8).unwrap(), encode_compact(&7_u8).unwrap());
+    }
+
+    /// A checksum that is always exactly its 4 big endian bytes, never messagepack.
+    #[derive(PartialEq, Debug, Clone, SerializedBytes)]
+    #[serialized_bytes(with = "checksum_bytes")]
+    struct Checksum(u32);
+
+    mod checksum_bytes {
+        use super::Checksum;
+        use holochain_serialized_bytes::prelude::*;
+        use holochain_serialized_bytes::DeserializeError;
+        use holochain_serialized_bytes::DeserializeErrorKind;
+
+        pub fn to_bytes(checksum: &Checksum) -> Result<Vec<u8>, SerializedBytesError> {
+            Ok(checksum.0.to_be_bytes().to_vec())
+        }
+
+        pub fn from_bytes(bytes: &[u8]) -> Result<Checksum, SerializedBytesError> {
+            bytes
+                .try_into()
+                .map(|bytes| Checksum(u32::from_be_bytes(bytes)))
+                .map_err(|_| {
+                    SerializedBytesError::Deserialize(DeserializeError {
+                        kind: DeserializeErrorKind::LengthMismatch,
+                        offset: None,
+                        type_name: "Checksum".into(),
+                        path: String::new(),
+                        message: format!("expected 4 bytes, got {}", bytes.len()),
+                    })
+                })
+        }
+    }
+
+    #[test]
+    fn custom_encoding() {
+        let checksum = Checksum(0xdeadbeef);
+        let sb = SerializedBytes::try_from(&checksum).unwrap();
+        assert_eq!(&[0xde, 0xad, 0xbe, 0xef], sb.bytes());
+        assert_eq!(checksum, Checksum::try_from(sb.clone()).unwrap());
+        assert_eq!(
+            checksum,
+            Checksum::try_from(SerializedBytesRef::from(&sb)).unwrap()
+        );
+
+        // the module decides what is valid, messagepack or not
+        let not_four_bytes = SerializedBytes::try_from(()).unwrap();
+        match Checksum::try_from(not_four_bytes) {
+            Err(SerializedBytesError::Deserialize(err)) => {
+                assert_eq!(DeserializeErrorKind::LengthMismatch, err.kind);
+            }
+            other => panic!("expected a length mismatch, got {:?}", other),
+        }
+
+        // nested in a messagepack type it is a bin like any other SerializedBytes
+        assert_eq!(
+            vec![0xc4, 0x04, 0xde, 0xad, 0xbe, 0xef],
+            encode(&sb).unwrap()
+        );
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum ZomeError {
+        Serialization(SerializedBytesError),
+        Io(String),
+    }
+
+    impl From<SerializedBytesError> for ZomeError {
+        fn from(err: SerializedBytesError) -> Self {
+            ZomeError::Serialization(err)
+        }
+    }
+
+    // for SerializedBytesCodec
+    impl From<std::io::Error> for ZomeError {
+        fn from(err: std::io::Error) -> Self {
+            ZomeError::Io(err.to_string())
+        }
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug, Clone, SerializedBytes)]
+    #[serialized_bytes(error = "ZomeError")]
+    struct ZomeInput {
+        name: String,
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+    struct ZomeOutput(u32);
+    holochain_serial!(ZomeOutput => ZomeError);
+
+    #[test]
+    fn custom_error() {
+        fn call(input: SerializedBytes) -> Result<SerializedBytes, ZomeError> {
+            let input = ZomeInput::try_from(input)?;
+            SerializedBytes::try_from(ZomeOutput(input.name.len() as u32))
+        }
+
+        let input = SerializedBytes::try_from(ZomeInput {
+            name: "alice".into(),
+        })
+        .unwrap();
+        assert_eq!(
+            ZomeOutput(5),
+            ZomeOutput::try_from(call(input).unwrap()).unwrap()
+        );
+
+        // errors from encoding and decoding come out as the custom error
+        match call(SerializedBytes::try_from(ZomeOutput(5)).unwrap()) {
+            Err(ZomeError::Serialization(SerializedBytesError::Deserialize(err))) => {
+                assert_eq!(DeserializeErrorKind::TypeMismatch, err.kind);
+            }
+            other => panic!("expected a type mismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn render() {
+        let sb = SerializedBytes::from(UnsafeBytes::from(vec![
+            0x86, // map of 6
+            0xa1, b'a', 0x92, 0x01, 0x02, // "a": [1, 2]
+            0xa1, b'u', 0xcf, 0, 0, 0, 0, 0, 0, 0, 0x05, // "u": 5 as a u64
+            0xa1, b'i', 0xd3, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+            0xff, // "i": -1 as an i64
+            0xa1, b'b', 0xc4, 0x03, 0x01, 0x02, 0x03, // "b": bin
+            0xa1, b'e', 0xd5, 0x05, 0xaa, 0xbb, // "e": ext type 5
+            0xa1, b'f', 0xca, 0x3f, 0xc0, 0x00, 0x00, // "f": 1.5 as an f32
+        ]));
+
+        assert_eq!(
+            r#"{"a":[1,2],"u":5,"i":-1,"b":[1,2,3],"e":{"$ext":[5,[170,187]]},"f":1.5}"#,
+            sb.display_with(RenderOptions::JSON).to_string()
+        );
+        assert_eq!(
+            r#"{"a": [1, 2], "u": 5_u64, "i": -1_i64, "b": h'010203', "e": ext(5, h'aabb'), "f": 1.5_f32}"#,
+            sb.display_with(RenderOptions::DIAGNOSTIC).to_string()
+        );
+        assert_eq!(
+            r#"{
+  "a": [
+    1,
+    2
+  ],
+  "u": 5,
+  "i": -1,
+  "b": "010203",
+  "e": {"$ext":[5,"aabb"]},
+  "f": 1.5
+}"#,
+            sb.display_with(RenderOptions::PRETTY_JSON).to_string()
+        );
+        assert_eq!(
+            r#"{"a":[1,2],"u":5,"i":-1,"b":"AQID","#.to_string()
+                + r#""e":{"$ext":[5,"qrs="]},"f":1.5}"#,
+            sb.display_with(RenderOptions {
+                bin: BinFormat::Base64,
+                ..RenderOptions::JSON
+            })
+            .to_string()
+        );
+        assert_eq!(
+            r#"{"a": [1, 2], "u": 5_u64, "i": -1_i64, "b": bin[1, …(+2 bytes)], "e": ext(5, bin[170, …(+1 bytes)]), "f": 1.5_f32}"#,
+            sb.display_with(RenderOptions {
+                bin: BinFormat::Array,
+                max_bin_len: 1,
+                ..RenderOptions::DIAGNOSTIC
+            })
+            .to_string()
+        );
+
+        // JSON renderings are always JSON, whatever the keys and however much is left out
+        let keys = SerializedBytes::from(UnsafeBytes::from(vec![
+            0x83, // map of 3
+            0x07, 0xc4, 0x03, 0x01, 0x02, 0x03, // 7: bin
+            0xa1, b'k', 0x81, 0xa1, b'x', 0xc0, // "k": {"x": null}
+            0x91, 0x01, 0x81, 0xc3, 0x02, // [1]: {true: 2}
+        ]));
+        let json = keys
+            .display_with(RenderOptions {
+                max_bin_len: 1,
+                ..RenderOptions::JSON
+            })
+            .to_string();
+        assert_eq!(
+            r#"{"$map":[[7,[1,"…(+2 bytes)"]],["k",{"x":null}],[[1],{"$map":[[true,2]]}]]}"#,
+            json
+        );
+        serde_json::from_str::<Value>(&json).unwrap();
+        let pretty = keys.display_with(RenderOptions::PRETTY_JSON).to_string();
+        assert_eq!(
+            r#"{"$map":[
+  [7,"010203"],
+  ["k",{
+    "x": null
+  }],
+  [[
+    1
+  ],{"$map":[
+    [true,2]
+  ]}]
+]}"#,
+            pretty
+        );
+        serde_json::from_str::<Value>(&pretty).unwrap();
+
+        // the same rendering for borrowed bytes
+        assert_eq!(
+            sb.display_with(RenderOptions::DIAGNOSTIC).to_string(),
+            SerializedBytesRef::from(&sb)
+                .display_with(RenderOptions::DIAGNOSTIC)
+                .to_string()
+        );
+
+        // anything that isn't exactly one value says why
+        let invalid = SerializedBytes::from(UnsafeBytes::from(vec![0x92, 0x01, 0xcd]));
+        assert_eq!(
+            "<invalid msgpack: unexpected end of input in value at byte 2>",
+            invalid.display_with(RenderOptions::JSON).to_string()
+        );
+        let trailing = SerializedBytes::from(UnsafeBytes::from(vec![0xc0, 0xc0]));
+        assert_eq!(
+            "<invalid msgpack: 1 trailing bytes after value>",
+            trailing.display_with(RenderOptions::DIAGNOSTIC).to_string()
+        );
+    }
+
+    #[test_fuzz::test_fuzz]
+    fn debug_any_bytes(bytes: Vec<u8>) {
+        // whatever the bytes are this renders something rather than panicking
+        let sb = SerializedBytes::from(UnsafeBytes::from(bytes));
+        let debug = format!("{:?}", sb);
+        assert_eq!(debug, sb.display_with(RenderOptions::JSON).to_string());
+        assert_eq!(debug, format!("{:?}", SerializedBytesRef::from(sb.bytes())));
+    }
+
+    #[test]
+    fn debug_untrusted_bytes() {
+        // a str that isn't UTF-8
+        debug_any_bytes(vec![0xa2, 0xff, 0xfe]);
+        assert_eq!(
+            "\"\u{fffd}\u{fffd}\"",
+            format!(
+                "{:?}",
+                SerializedBytes::from(UnsafeBytes::from(vec![0xa2, 0xff, 0xfe]))
+            )
+        );
+        // the reserved marker
+        debug_any_bytes(vec![0xc1]);
+        // a declared length far beyond the input
+        debug_any_bytes(vec![0xdd, 0xff, 0xff, 0xff, 0xff]);
+        // far deeper than anything recursive could handle
+        let mut deep = vec![0x91; 100_000];
+        deep.push(0xc0);
+        debug_any_bytes(deep);
+        assert_eq!(
+            "<invalid msgpack: reserved marker 0xc1 at byte 0>",
+            format!("{:?}", SerializedBytes::from(UnsafeBytes::from(vec![0xc1])))
+        );
+        // a map with keys that aren't strings
+        debug_any_bytes(vec![0x81, 0x92, 0x01, 0x02, 0xc0]);
+        assert_eq!(
+            r#"{"$map":[[[1,2],null]]}"#,
+            format!(
+                "{:?}",
+                SerializedBytes::from(UnsafeBytes::from(vec![0x81, 0x92, 0x01, 0x02, 0xc0]))
+            )
+        );
+        // maps as the keys of maps as the keys of maps, far too deep to recurse
+        let mut deep_keys = vec![0x81; 100_000];
+        deep_keys.extend(vec![0xc0; 100_001]);
+        debug_any_bytes(deep_keys);
+    }
+
+    #[test]
+    fn dynamic_values() {
+        use holochain_serialized_bytes::Value;
+        use std::collections::BTreeMap;
+
+        #[derive(Serialize, Debug)]
+        struct Header {
+            seq: u32,
+            delta: i32,
+            #[serde(with = "serde_bytes")]
+            author: Vec<u8>,
+        }
+
+        #[derive(Serialize, Debug)]
+        struct Entry {
+            header: Header,
+            tags: Vec<String>,
+            by_id: BTreeMap<u32, f64>,
+        }
+
+        #[derive(Serialize, Debug)]
+        struct Record {
+            entry: Entry,
+        }
+
+        let sb = SerializedBytes::from(UnsafeBytes::from(
+            encode(&Record {
+                entry: Entry {
+                    header: Header {
+                        seq: 7,
+                        delta: -3,
+                        author: vec![1, 2],
+                    },
+                    tags: vec!["a".into(), "b".into()],
+                    by_id: [(2, 0.5)].into_iter().collect(),
+                },
+            })
+            .unwrap(),
+        ));
+
+        let header = Value::Map(vec![
+            (Value::Str("seq".into()), Value::UInt(7)),
+            (Value::Str("delta".into()), Value::Int(-3)),
+            (Value::Str("author".into()), Value::Bin(vec![1, 2])),
+        ]);
+        let record = Value::Map(vec![(
+            Value::Str("entry".into()),
+            Value::Map(vec![
+                (Value::Str("header".into()), header.clone()),
+                (
+                    Value::Str("tags".into()),
+                    Value::Array(vec![Value::Str("a".into()), Value::Str("b".into())]),
+                ),
+                (
+                    Value::Str("by_id".into()),
+                    Value::Map(vec![(Value::UInt(2), Value::F64(0.5))]),
+                ),
+            ]),
+        )]);
+        assert_eq!(record, sb.to_value().unwrap());
+
+        // paths through maps, arrays and integer keys
+        assert_eq!(Some(Value::UInt(7)), sb.get("entry.header.seq").unwrap());
+        assert_eq!(Some(header), sb.get("entry.header").unwrap());
+        assert_eq!(
+            Some("b"),
+            sb.get("entry.tags.1")
+                .unwrap()
+                .as_ref()
+                .and_then(Value::as_str)
+        );
+        assert_eq!(
+            Some(0.5),
+            sb.get("entry.by_id.2").unwrap().and_then(|v| v.as_f64())
+        );
+        assert_eq!(
+            Some(-3),
+            sb.get("entry.header.delta")
+                .unwrap()
+                .and_then(|v| v.as_i64())
+        );
+        assert_eq!(Some(record), sb.get("").unwrap());
+        for missing in [
+            "entry.footer",
+            "entry.tags.2",
+            "entry.tags.first",
+            "entry.header.seq.more",
+        ] {
+            assert_eq!(None, sb.get(missing).unwrap());
+        }
+
+        // ext types and anything else messagepack has come through as they are
+        let ext =
+            SerializedBytes::from(UnsafeBytes::from(vec![0x92, 0xd5, 0x05, 0xaa, 0xbb, 0xc0]));
+        assert_eq!(
+            Value::Array(vec![Value::Ext(5, vec![0xaa, 0xbb]), Value::Nil]),
+            ext.to_value().unwrap()
+        );
+
+        // only the bytes on the way to the value are read
+        let truncated = SerializedBytes::from(UnsafeBytes::from(vec![
+            0x82, 0xa1, b'a', 0x01, 0xa1, b'b', 0xa5, b'x',
+        ]));
+        assert_eq!(Some(Value::UInt(1)), truncated.get("a").unwrap());
+        match truncated.to_value() {
+            Err(SerializedBytesError::Deserialize(err)) => {
+                assert_eq!(DeserializeErrorKind::UnexpectedEof, err.kind);
+                assert_eq!(Some(6), err.offset);
+            }
+            other => panic!("expected an unexpected eof, got {:?}", other),
+        }
+        match truncated.get("b") {
+            Err(SerializedBytesError::Deserialize(err)) => {
+                assert_eq!(DeserializeErrorKind::UnexpectedEof, err.kind);
+                assert_eq!("b", err.path);
+            }
+            other => panic!("expected an unexpected eof, got {:?}", other),
+        }
+
+        // nesting is limited so dropping a value can't overflow the stack
+        let nested = |depth| {
+            let mut bytes = vec![0x91; depth];
+            bytes.push(0xc0);
+            SerializedBytes::from(UnsafeBytes::from(bytes))
+        };
+        assert!(nested(1024).to_value().is_ok());
+        match nested(100_000).to_value() {
+            Err(SerializedBytesError::Deserialize(err)) => {
+                assert_eq!(DeserializeErrorKind::DepthExceeded, err.kind);
+                assert_eq!(Some(1024), err.offset);
+            }
+            other => panic!("expected depth exceeded, got {:?}", other),
+        }
+
+        // lengths aren't trusted, 1 MiB of array32 headers that each claim the rest of the input
+        // fails rather than allocating for every claim
+        let headers = (1 << 20) / 5;
+        let mut bytes = Vec::with_capacity(headers * 5);
+        for i in 0..headers {
+            bytes.push(0xdd);
+            bytes.extend_from_slice(&(((headers - i - 1) * 5) as u32).to_be_bytes());
+        }
+        let claims = SerializedBytes::from(UnsafeBytes::from(bytes));
+        assert!(claims.to_value().is_err());
+        assert!(claims.to_json(JsonOptions::LOSSLESS).is_err());
+
+        // strs must be UTF-8, unlike bins
+        let invalid = SerializedBytes::from(UnsafeBytes::from(vec![0xa1, 0xff]));
+        match invalid.to_value() {
+            Err(SerializedBytesError::Deserialize(err)) => {
+                assert_eq!(DeserializeErrorKind::InvalidUtf8, err.kind);
+            }
+            other => panic!("expected invalid utf8, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn partial_field_access() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct AgentKey(#[serde(with = "serde_bytes")] Vec<u8>);
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Header {
+            seq: u32,
+            author: AgentKey,
+            zome: String,
+        }
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Entry {
+            header: Header,
+            #[serde(with = "serde_bytes")]
+            payload: Vec<u8>,
+        }
+
+        let entry = Entry {
+            header: Header {
+                seq: 3,
+                author: AgentKey(vec![7; 32]),
+                zome: "profiles".into(),
+            },
+            payload: vec![0; 10_000],
+        };
+        let sb = SerializedBytes::from(UnsafeBytes::from(encode(&entry).unwrap()));
+
+        assert_eq!(
+            Some(&entry.header.author),
+            sb.field::<AgentKey>(&["header", "author"])
+                .unwrap()
+                .as_ref()
+        );
+        assert_eq!(
+            Some(&entry.header),
+            sb.field::<Header>(&["header"]).unwrap().as_ref()
+        );
+        assert_eq!(Some(entry), sb.field::<Entry>(&[]).unwrap());
+        assert_eq!(None, sb.field::<u32>(&["header", "signature"]).unwrap());
+        assert_eq!(None, sb.field::<u32>(&["header", "seq", "more"]).unwrap());
+
+        // borrowed straight out of the bytes
+        let sb_ref = SerializedBytesRef::from(&sb);
+        assert_eq!(
+            Some("profiles"),
+            sb_ref.field::<&str>(&["header", "zome"]).unwrap()
+        );
+
+        // errors point at the field from the root of the bytes
+        let author = sb.bytes().windows(2).position(|w| w == [0xc4, 32]);
+        match sb.field::<u32>(&["header", "author"]) {
+            Err(SerializedBytesError::Deserialize(err)) => {
+                assert_eq!(DeserializeErrorKind::TypeMismatch, err.kind);
+                assert_eq!("header.author", err.path);
+                assert_eq!(author, err.offset);
+            }
+            other => panic!("expected a type mismatch, got {:?}", other),
+        }
+        #[derive(Deserialize, Debug)]
+        struct WrongHeader {
+            #[allow(dead_code)]
+            author: bool,
+        }
+        match sb.field::<WrongHeader>(&["header"]) {
+            Err(SerializedBytesError::Deserialize(err)) => {
+                assert_eq!(DeserializeErrorKind::TypeMismatch, err.kind);
+                assert_eq!("header.author", err.path);
+                assert_eq!(author, err.offset);
+            }
+            other => panic!("expected a type mismatch, got {:?}", other),
+        }
+
+        // nothing after the field is read, so a truncated payload doesn't matter
+        let truncated = &sb.bytes()[..sb.bytes().len() - 1];
+        assert_eq!(
+            Some(3),
+            SerializedBytesRef::from(truncated)
+                .field::<u32>(&["header", "seq"])
+                .unwrap()
+        );
+        match SerializedBytesRef::from(truncated).field::<Vec<u8>>(&["payload"]) {
+            Err(SerializedBytesError::Deserialize(err)) => {
+                assert_eq!(DeserializeErrorKind::UnexpectedEof, err.kind);
+                assert_eq!("payload", err.path);
+            }
+            other => panic!("expected an unexpected eof, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn json_round_trip() {
+        use std::collections::BTreeMap;
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Tagged {
+            #[serde(rename = "$bin")]
+            bin: u8,
+        }
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Everything {
+            unit: (),
+            yes: bool,
+            small: u8,
+            negative: i16,
+            big: u64,
+            very_negative: i64,
+            half: f32,
+            third: f64,
+            nan: f64,
+            text: String,
+            #[serde(with = "serde_bytes")]
+            bin: Vec<u8>,
+            list: Vec<u32>,
+            by_id: BTreeMap<u32, String>,
+            tagged: Tagged,
+            empty: Vec<()>,
+        }
+
+        let everything = Everything {
+            unit: (),
+            yes: true,
+            small: 200,
+            negative: -300,
+            big: u64::MAX,
+            very_negative: i64::MIN,
+            half: 0.5,
+            third: 1.0 / 3.0,
+            nan: f64::NAN,
+            text: "\u{1f600} \"quoted\"".into(),
+            bin: vec![0, 1, 2, 255],
+            list: vec![1, 70_000],
+            by_id: [(1, "one".into()), (2, "two".into())].into_iter().collect(),
+            tagged: Tagged { bin: 1 },
+            empty: vec![],
+        };
+        let sb = SerializedBytes::from(UnsafeBytes::from(encode(&everything).unwrap()));
+
+        let json = sb.to_json(JsonOptions::LOSSLESS).unwrap();
+        assert_eq!(
+            serde_json::json!({
+                "unit": null,
+                "yes": true,
+                "small": 200,
+                "negative": -300,
+                "big": {"$int": "18446744073709551615"},
+                "very_negative": {"$int": "-9223372036854775808"},
+                "half": {"$f32": 0.5},
+                "third": 1.0 / 3.0,
+                "nan": {"$f64": "NaN"},
+                "text": "\u{1f600} \"quoted\"",
+                "bin": {"$bin": "AAEC/w=="},
+                "list": [1, 70000],
+                "by_id": {"$map": [[1, "one"], [2, "two"]]},
+                "tagged": {"$map": [["$bin", 1]]},
+                "empty": [],
+            }),
+            json
+        );
+
+        // through JSON text and back to exactly the same bytes
+        let text = serde_json::to_string(&json).unwrap();
+        let back = SerializedBytes::from_json_value(&serde_json::from_str(&text).unwrap()).unwrap();
+        assert_eq!(sb.bytes(), back.bytes());
+
+        // ext values and maps that can't be objects
+        for bytes in [
+            vec![0x92, 0xd5, 0x05, 0xaa, 0xbb, 0xc7, 0x03, 0xfe, 1, 2, 3],
+            vec![0x82, 0xa1, b'a', 0x01, 0xa1, b'a', 0x02],
+            vec![0x81, 0x92, 0x01, 0x02, 0xc2],
+            vec![0x81, 0xa4, b'$', b'm', b'a', b'p', 0x90],
+        ] {
+            let sb = SerializedBytes::from(UnsafeBytes::from(bytes));
+            let json = sb.to_json(JsonOptions::default()).unwrap();
+            assert_eq!(
+                sb.bytes(),
+                SerializedBytes::from_json_value(&json).unwrap().bytes(),
+                "{}",
+                json
+            );
+        }
+
+        // NaNs with a sign or payload keep their bits
+        for bytes in [
+            encode(&-f64::NAN).unwrap(),
+            encode(&f64::from_bits(0x7ff0_0000_0000_0001)).unwrap(),
+            encode(&-f32::NAN).unwrap(),
+        ] {
+            let sb = SerializedBytes::from(UnsafeBytes::from(bytes));
+            let json = sb.to_json(JsonOptions::LOSSLESS).unwrap();
+            assert_eq!(
+                sb.bytes(),
+                SerializedBytes::from_json_value(&json).unwrap().bytes(),
+                "{}",
+                json
+            );
+        }
+        assert_eq!(
+            serde_json::json!({"$f64": "0xfff8000000000000"}),
+            SerializedBytes::from(UnsafeBytes::from(encode(&-f64::NAN).unwrap()))
+                .to_json(JsonOptions::LOSSLESS)
+                .unwrap()
+        );
+
+        // deeper than serde_json nests, and as deep as rmp_serde nests, still converts back
+        let nested = |depth: usize| {
+            SerializedBytes::from(UnsafeBytes::from([vec![0x91; depth], vec![0xc0]].concat()))
+        };
+        decode::<_, serde::de::IgnoredAny>(nested(129).bytes()).unwrap();
+        for depth in [129, 1024] {
+            let deep = nested(depth);
+            let json = deep.to_json(JsonOptions::LOSSLESS).unwrap();
+            assert_eq!(
+                deep.bytes(),
+                SerializedBytes::from_json_value(&json).unwrap().bytes()
+            );
+        }
+        match nested(1025).to_json(JsonOptions::LOSSLESS) {
+            Err(SerializedBytesError::Deserialize(err)) => {
+                assert_eq!(DeserializeErrorKind::DepthExceeded, err.kind);
+            }
+            other => panic!("expected a depth error, got {:?}", other),
+        }
+
+        // simpler JSON that doesn't convert back to the same bytes
+        let simple = JsonOptions {
+            bin: JsonBin::Base64,
+            safe_integers: false,
+        };
+        let json = sb.to_json(simple).unwrap();
+        assert_eq!(serde_json::json!("AAEC/w=="), json["bin"]);
+        assert_eq!(serde_json::json!(u64::MAX), json["big"]);
+        assert_ne!(
+            sb.bytes(),
+            SerializedBytes::from_json_value(&json).unwrap().bytes()
+        );
+
+        // JSON from anywhere else works as long as it follows the conventions
+        let from_client = SerializedBytes::from_json_value(&serde_json::json!({
+            "author": {"$bin": "AQID"},
+            "seq": 7,
+        }))
+        .unwrap();
+        assert_eq!(
+            Some(vec![1, 2, 3]),
+            from_client
+                .field::<serde_bytes::ByteBuf>(&["author"])
+                .unwrap()
+                .map(|b| b.into_vec())
+        );
+        match SerializedBytes::from_json_value(&serde_json::json!({
+            "entries": [{"author": {"$bin": "not base64!"}}],
+        })) {
+            Err(SerializedBytesError::Deserialize(err)) => {
+                assert_eq!(DeserializeErrorKind::InvalidValue, err.kind);
+                assert_eq!("entries.0.author.$bin", err.path);
+            }
+            other => panic!("expected invalid base64, got {:?}", other),
+        }
+    }
+
     #[test]
     fn default_test() {
         assert_eq!(&vec![192_u8], SerializedBytes::default().bytes());
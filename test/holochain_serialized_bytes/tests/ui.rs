@@ -0,0 +1,8 @@
+/// Errors `#[derive(SerializedBytes)]` itself reports, with their spans.
+/// Missing traits are rustc's errors, whose wording changes between toolchains, so they are
+/// doctests in `src/lib.rs` that only check the error code.
+/// Run with `TRYBUILD=overwrite` to update the expected output after changing the derive.
+#[test]
+fn derive_errors() {
+    trybuild::TestCases::new().compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,7 @@
+use holochain_serialized_bytes::prelude::*;
+
+#[derive(Debug, SerializedBytes)]
+#[serialized_bytes(compact, with = "foo_bytes")]
+struct Foo(u32);
+
+fn main() {}
@@ -0,0 +1,8 @@
+use holochain_serialized_bytes::prelude::*;
+
+#[derive(Debug, SerializedBytes)]
+#[serialized_bytes(with = "foo_bytes")]
+#[serialized_bytes(with = "bar_bytes")]
+struct Foo(u32);
+
+fn main() {}
@@ -0,0 +1,9 @@
+use holochain_serialized_bytes::prelude::*;
+
+#[derive(SerializedBytes)]
+union Either {
+    a: u32,
+    b: f32,
+}
+
+fn main() {}
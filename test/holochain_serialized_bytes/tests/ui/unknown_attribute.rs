@@ -0,0 +1,9 @@
+use holochain_serialized_bytes::prelude::*;
+
+#[derive(Serialize, Deserialize, Debug, SerializedBytes)]
+#[serialized_bytes(compat)]
+struct Foo {
+    inner: String,
+}
+
+fn main() {}